@@ -21,6 +21,7 @@
           packages = with pkgs; [
             libsForQt5.kdialog
             gnome.zenity
+            poppler_utils
           ];
           
           buildInputs = [
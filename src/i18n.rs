@@ -0,0 +1,613 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum Language {
+    #[default]
+    English,
+    Arabic,
+}
+
+impl Language {
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Arabic => "العربية",
+        }
+    }
+
+    /// Whether the UI mirrors to read right to left
+    pub fn is_rtl(self) -> bool {
+        self == Language::Arabic
+    }
+
+    pub fn strings(self) -> &'static Strings {
+        match self {
+            Language::English => &ENGLISH,
+            Language::Arabic => &ARABIC,
+        }
+    }
+}
+
+/// UI labels of one language, Arabic ones still have to go through `fix_text` before egui shows them
+pub struct Strings {
+    pub import_csv: &'static str,
+    pub import_template: &'static str,
+    pub template_layout: &'static str,
+    pub templates: &'static str,
+    pub preview_selected: &'static str,
+    pub previous: &'static str,
+    pub next: &'static str,
+    pub preview_widest: &'static str,
+    pub create: &'static str,
+    pub preferences: &'static str,
+    pub send_email: &'static str,
+    pub retry_queued: &'static str,
+    pub skip_sent: &'static str,
+    pub font_size: &'static str,
+    pub serial_column: &'static str,
+    pub drop_rows: &'static str,
+    pub never: &'static str,
+    pub any_empty: &'static str,
+    pub required_empty: &'static str,
+    pub required_columns: &'static str,
+    pub fixed_page_size: &'static str,
+    pub fit: &'static str,
+    pub draw_areas: &'static str,
+    pub add_csv: &'static str,
+    pub choose_template: &'static str,
+    pub draw: &'static str,
+    pub clear: &'static str,
+    pub remove: &'static str,
+    pub add_qr_code: &'static str,
+    pub add_barcode: &'static str,
+    pub add_image: &'static str,
+    pub add_text: &'static str,
+    pub line_height: &'static str,
+    pub max_lines: &'static str,
+    pub max_chars: &'static str,
+    pub fallback: &'static str,
+    pub letter_spacing: &'static str,
+    pub word_spacing: &'static str,
+    pub pick_template_by_column: &'static str,
+    pub imported_template: &'static str,
+    pub output_directory: &'static str,
+    pub browse: &'static str,
+    pub output_format: &'static str,
+    pub quality: &'static str,
+    pub font: &'static str,
+    pub threads: &'static str,
+    pub send_threads: &'static str,
+    pub theme: &'static str,
+    pub system: &'static str,
+    pub light: &'static str,
+    pub dark: &'static str,
+    pub language: &'static str,
+    pub smtp_server: &'static str,
+    pub email: &'static str,
+    pub password: &'static str,
+    pub save: &'static str,
+    pub clear_credentials: &'static str,
+    pub existing_certificates: &'static str,
+    pub overwrite: &'static str,
+    pub skip_existing: &'static str,
+    pub new_folder: &'static str,
+    pub cancel: &'static str,
+    pub preview: &'static str,
+    pub create_certificates: &'static str,
+    pub open_output_folder: &'static str,
+    pub add_email_credentials: &'static str,
+    pub duplicate_recipients: &'static str,
+    pub send_once: &'static str,
+    pub send_all: &'static str,
+    pub subfolder_by: &'static str,
+    pub none: &'static str,
+    pub last_results: &'static str,
+    pub succeeded: &'static str,
+    pub failures: &'static str,
+    pub elapsed: &'static str,
+    pub ignored_columns: &'static str,
+    pub background: &'static str,
+    pub manifest: &'static str,
+    pub in_order: &'static str,
+    pub display_name: &'static str,
+    pub reply_to: &'static str,
+    pub organization: &'static str,
+    pub vertical_align: &'static str,
+    pub anchor: &'static str,
+    pub center: &'static str,
+    pub baseline: &'static str,
+    pub top: &'static str,
+    pub middle: &'static str,
+    pub bottom: &'static str,
+    pub padding: &'static str,
+    pub restore_layout: &'static str,
+    pub restore_prompt: &'static str,
+    pub restore: &'static str,
+    pub discard: &'static str,
+    pub add_date: &'static str,
+    pub add_combined: &'static str,
+    pub date_format: &'static str,
+    pub date_column: &'static str,
+    pub today: &'static str,
+    pub generate_selected: &'static str,
+    pub attachment_name: &'static str,
+    pub email_body: &'static str,
+    pub test_connection: &'static str,
+    pub first_row_is_data: &'static str,
+    pub overflows: &'static str,
+    pub shortened_names: &'static str,
+    pub retry_failed: &'static str,
+    pub file_name: &'static str,
+    pub status: &'static str,
+    pub pending: &'static str,
+    pub sent: &'static str,
+    pub failed: &'static str,
+    pub pdf_dpi: &'static str,
+    pub output_scale: &'static str,
+    pub log_level: &'static str,
+    pub log_to_file: &'static str,
+    pub export_mapping: &'static str,
+    pub import_mapping: &'static str,
+    pub supersample: &'static str,
+    pub confirm_send: &'static str,
+    pub recipients: &'static str,
+    pub email_column: &'static str,
+    pub from: &'static str,
+    pub subject: &'static str,
+    pub send: &'static str,
+    pub invalid_date_format: &'static str,
+    pub qr_code: &'static str,
+    pub qr_data: &'static str,
+    pub barcode: &'static str,
+    pub barcode_data: &'static str,
+    pub image: &'static str,
+    pub text: &'static str,
+    pub combined: &'static str,
+    pub date: &'static str,
+    pub column: &'static str,
+    pub template: &'static str,
+    pub invalid_template: &'static str,
+    pub invalid_image: &'static str,
+    pub needs_complete_row: &'static str,
+    pub needs_two_columns: &'static str,
+    pub select_row_to_preview: &'static str,
+    pub select_row_to_generate: &'static str,
+    pub creating: &'static str,
+    pub sending: &'static str,
+    pub finished: &'static str,
+    pub worker_panicked: &'static str,
+    pub no_email_column: &'static str,
+    pub saved: &'static str,
+    pub open_folder_failed: &'static str,
+    pub exported_to: &'static str,
+    pub mapping_imported: &'static str,
+    pub not_in_csv: &'static str,
+    pub ignored_in_csv: &'static str,
+    pub connected: &'static str,
+    pub sequential: &'static str,
+    pub uuid: &'static str,
+    pub stretch: &'static str,
+    pub contain: &'static str,
+    pub cover: &'static str,
+    pub port: &'static str,
+    pub records: &'static str,
+    pub dropped_rows: &'static str,
+    pub unreadable_rows: &'static str,
+    pub valid_emails: &'static str,
+    pub font_not_installed: &'static str,
+    pub fallback_glyphs: &'static str,
+    pub missing_glyphs: &'static str,
+    pub every_certificate_imported: &'static str,
+    pub differs_from_layout: &'static str,
+    pub column_hint: &'static str,
+    pub example: &'static str,
+    pub fallback_hint: &'static str,
+    pub baseline_hint: &'static str,
+    pub serial_hint: &'static str,
+    pub preview_widest_hint: &'static str,
+    pub generate_selected_hint: &'static str,
+    pub retry_queued_hint: &'static str,
+    pub skip_sent_hint: &'static str,
+    pub first_row_hint: &'static str,
+    pub arrow_keys_hint: &'static str,
+    pub retry_failed_hint: &'static str,
+    pub output_scale_hint: &'static str,
+    pub supersample_hint: &'static str,
+    pub pdf_dpi_hint: &'static str,
+    pub manifest_hint: &'static str,
+    pub in_order_hint: &'static str,
+    pub log_file_hint: &'static str,
+    pub send_threads_hint: &'static str,
+    pub display_name_hint: &'static str,
+    pub attachment_name_hint: &'static str,
+    pub certificates_already_exist: &'static str,
+}
+
+const ENGLISH: Strings = Strings {
+    import_csv: "Import CSV",
+    import_template: "Import Template",
+    template_layout: "Template Layout",
+    templates: "Templates",
+    preview_selected: "Preview selected",
+    previous: "Previous",
+    next: "Next",
+    preview_widest: "Preview widest",
+    create: "Create",
+    preferences: "Preferences",
+    send_email: "Send Email",
+    retry_queued: "Retry queued",
+    skip_sent: "Skip already sent",
+    font_size: "Font size",
+    serial_column: "Serial column",
+    drop_rows: "Drop rows",
+    never: "Never",
+    any_empty: "With any empty cell",
+    required_empty: "With empty required cells",
+    required_columns: "Required columns",
+    fixed_page_size: "Fixed page size",
+    fit: "Fit",
+    draw_areas: "Draw Areas",
+    add_csv: "Add a CSV file",
+    choose_template: "Choose a template",
+    draw: "Draw",
+    clear: "Clear",
+    remove: "Remove",
+    add_qr_code: "Add QR code",
+    add_barcode: "Add barcode",
+    add_image: "Add image",
+    add_text: "Add text",
+    line_height: "Line height",
+    max_lines: "Max lines",
+    max_chars: "Max characters",
+    fallback: "Text when empty",
+    letter_spacing: "Letter spacing",
+    word_spacing: "Word spacing",
+    pick_template_by_column: "Pick template by column",
+    imported_template: "Imported template",
+    output_directory: "Output directory",
+    browse: "Browse",
+    output_format: "Output format",
+    quality: "Quality",
+    font: "Font",
+    threads: "Threads",
+    send_threads: "Sending threads",
+    theme: "Theme",
+    system: "System",
+    light: "Light",
+    dark: "Dark",
+    language: "Language",
+    smtp_server: "SMTP server",
+    email: "Email",
+    password: "Password",
+    save: "Save",
+    clear_credentials: "Clear credentials",
+    existing_certificates: "Existing Certificates",
+    overwrite: "Overwrite",
+    skip_existing: "Skip existing",
+    new_folder: "New folder",
+    cancel: "Cancel",
+    preview: "Preview",
+    create_certificates: "Create Certificates",
+    open_output_folder: "Open output folder",
+    add_email_credentials: "Add Email credentials",
+    duplicate_recipients: "Duplicate Recipients",
+    send_once: "Send once each",
+    send_all: "Send all",
+    subfolder_by: "Subfolder by",
+    none: "None",
+    last_results: "Last results",
+    succeeded: "Succeeded",
+    failures: "Failures",
+    elapsed: "Elapsed",
+    ignored_columns: "Ignored columns",
+    background: "Background",
+    manifest: "Write manifest",
+    in_order: "Generate in row order",
+    display_name: "Display name",
+    reply_to: "Reply to",
+    organization: "Organization",
+    vertical_align: "Vertical align",
+    anchor: "Anchor",
+    center: "Center",
+    baseline: "Baseline",
+    top: "Top",
+    middle: "Middle",
+    bottom: "Bottom",
+    padding: "Padding",
+    restore_layout: "Restore Layout",
+    restore_prompt: "The app didn't close cleanly, restore the autosaved layout?",
+    restore: "Restore",
+    discard: "Discard",
+    add_date: "Add date",
+    add_combined: "Add combined text",
+    date_format: "Date format",
+    date_column: "Date column",
+    today: "Today",
+    generate_selected: "Generate selected",
+    attachment_name: "Attachment name",
+    email_body: "Email body",
+    test_connection: "Test connection",
+    first_row_is_data: "First row is data",
+    overflows: "Text overflows",
+    shortened_names: "Shortened file names",
+    retry_failed: "Retry failed only",
+    file_name: "File name",
+    status: "Status",
+    pending: "Pending",
+    sent: "Sent",
+    failed: "Failed",
+    pdf_dpi: "PDF resolution",
+    output_scale: "Output scale",
+    log_level: "Log level",
+    log_to_file: "Write log file",
+    export_mapping: "Export mapping",
+    import_mapping: "Import mapping",
+    supersample: "Supersampling",
+    confirm_send: "Confirm sending",
+    recipients: "Recipients",
+    email_column: "Email column",
+    from: "From",
+    subject: "Subject",
+    send: "Send",
+    invalid_date_format: "Invalid date format",
+    qr_code: "QR code",
+    qr_data: "QR data",
+    barcode: "Barcode",
+    barcode_data: "Barcode data",
+    image: "Image",
+    text: "Text",
+    combined: "Combined",
+    date: "Date",
+    column: "Column",
+    template: "Template",
+    invalid_template: "Invalid template",
+    invalid_image: "Invalid image",
+    needs_complete_row: "Import a CSV with at least one complete row",
+    needs_two_columns: "The CSV needs at least two columns to name the certificates",
+    select_row_to_preview: "Select a row to preview",
+    select_row_to_generate: "Select a row to generate",
+    creating: "Creating...",
+    sending: "Sending...",
+    finished: "Finished!",
+    worker_panicked: "Worker thread panicked",
+    no_email_column: "No email column",
+    saved: "Saved",
+    open_folder_failed: "Failed to open output folder",
+    exported_to: "Exported to",
+    mapping_imported: "Mapping imported",
+    not_in_csv: "Not in this CSV",
+    ignored_in_csv: "Ignored in this CSV",
+    connected: "Connected and logged in",
+    sequential: "Sequential",
+    uuid: "UUID",
+    stretch: "Stretch",
+    contain: "Contain",
+    cover: "Cover",
+    port: "Port",
+    records: "Records",
+    dropped_rows: "Rows dropped for empty cells",
+    unreadable_rows: "Unreadable rows",
+    valid_emails: "Valid emails",
+    font_not_installed: "Font is not installed",
+    fallback_glyphs: "Drawn with a fallback font",
+    missing_glyphs: "No installed font can display",
+    every_certificate_imported: "Every certificate uses the imported template",
+    differs_from_layout: "Differs from the layout template, fields may be misplaced",
+    column_hint: "Insert a column's value with",
+    example: "For example",
+    fallback_hint: "Drawn when the row's value is empty",
+    baseline_hint: "Baseline puts the last line on the bottom edge",
+    serial_hint: "Adds a column with a unique ID per record",
+    preview_widest_hint: "Preview the widest value of every column at once",
+    generate_selected_hint: "Save the selected row's certificate wherever you choose",
+    retry_queued_hint: "Emails that failed to send are kept until they go out",
+    skip_sent_hint: "Don't send again to addresses this template was sent to",
+    first_row_hint: "For files without a header row, applies to the next import",
+    arrow_keys_hint: "Arrow keys flip through the rows too",
+    retry_failed_hint: "Generate only the rows that failed or overflowed",
+    output_scale_hint: "Smaller for lighter emails, larger for print",
+    supersample_hint: "Render larger and shrink on save for smoother text",
+    pdf_dpi_hint: "Resolution PDF templates are rendered at",
+    manifest_hint: "ID and column values of every certificate",
+    in_order_hint: "Files are written one by one in row order, slower",
+    log_file_hint: "Appends to a file in the config directory",
+    send_threads_hint: "Emails sent at the same time, raise it if your SMTP server allows",
+    display_name_hint: "Shown to recipients next to the address",
+    attachment_name_hint: "Empty names it like the certificate file",
+    certificates_already_exist: "Certificates that already exist",
+};
+
+// no latin words in here, fix_text reverses the whole string
+const ARABIC: Strings = Strings {
+    import_csv: "استيراد ملف البيانات",
+    import_template: "استيراد القالب",
+    template_layout: "تخطيط القالب",
+    templates: "القوالب",
+    preview_selected: "معاينة المحدد",
+    previous: "السابق",
+    next: "التالي",
+    preview_widest: "معاينة الأطول",
+    create: "إنشاء",
+    preferences: "الإعدادات",
+    send_email: "إرسال البريد",
+    retry_queued: "إعادة إرسال المؤجل",
+    skip_sent: "تخطي من أرسل لهم",
+    font_size: "حجم الخط",
+    serial_column: "عمود الرقم التسلسلي",
+    drop_rows: "حذف الصفوف",
+    never: "أبدا",
+    any_empty: "مع أي خلية فارغة",
+    required_empty: "مع خلايا مطلوبة فارغة",
+    required_columns: "الأعمدة المطلوبة",
+    fixed_page_size: "حجم صفحة ثابت",
+    fit: "الملاءمة",
+    draw_areas: "مناطق الرسم",
+    add_csv: "أضف ملف البيانات",
+    choose_template: "اختر قالبا",
+    draw: "رسم",
+    clear: "مسح",
+    remove: "إزالة",
+    add_qr_code: "إضافة رمز استجابة سريعة",
+    add_barcode: "إضافة رمز شريطي",
+    add_image: "إضافة صورة",
+    add_text: "إضافة نص",
+    line_height: "ارتفاع السطر",
+    max_lines: "أقصى عدد أسطر",
+    max_chars: "أقصى عدد أحرف",
+    fallback: "النص عند الفراغ",
+    letter_spacing: "تباعد الأحرف",
+    word_spacing: "تباعد الكلمات",
+    pick_template_by_column: "اختيار القالب حسب العمود",
+    imported_template: "القالب المستورد",
+    output_directory: "مجلد الحفظ",
+    browse: "استعراض",
+    output_format: "صيغة الحفظ",
+    quality: "الجودة",
+    font: "الخط",
+    threads: "عدد المسارات",
+    send_threads: "عدد مسارات الإرسال",
+    theme: "المظهر",
+    system: "النظام",
+    light: "فاتح",
+    dark: "داكن",
+    language: "اللغة",
+    smtp_server: "خادم البريد",
+    email: "البريد الالكتروني",
+    password: "كلمة المرور",
+    save: "حفظ",
+    clear_credentials: "مسح بيانات الدخول",
+    existing_certificates: "شهادات موجودة",
+    overwrite: "استبدال",
+    skip_existing: "تخطي الموجود",
+    new_folder: "مجلد جديد",
+    cancel: "إلغاء",
+    preview: "معاينة",
+    create_certificates: "إنشاء الشهادات",
+    open_output_folder: "فتح مجلد الحفظ",
+    add_email_credentials: "أضف بيانات البريد",
+    duplicate_recipients: "مستلمون مكررون",
+    send_once: "إرسال مرة واحدة لكل عنوان",
+    send_all: "إرسال الكل",
+    subfolder_by: "مجلد فرعي حسب",
+    none: "لا شيء",
+    last_results: "آخر النتائج",
+    succeeded: "نجح",
+    failures: "أخطاء",
+    elapsed: "المدة",
+    ignored_columns: "الأعمدة المتجاهلة",
+    background: "الخلفية",
+    manifest: "كتابة ملف البيان",
+    in_order: "الإنشاء بترتيب الصفوف",
+    display_name: "اسم المرسل",
+    reply_to: "عنوان الرد",
+    organization: "الجهة",
+    vertical_align: "المحاذاة العمودية",
+    anchor: "نقطة الارتكاز",
+    center: "المركز",
+    baseline: "خط الأساس",
+    top: "أعلى",
+    middle: "وسط",
+    bottom: "أسفل",
+    padding: "الهامش الداخلي",
+    restore_layout: "استعادة التخطيط",
+    restore_prompt: "لم يغلق التطبيق بشكل سليم، هل تريد استعادة التخطيط المحفوظ تلقائيا؟",
+    restore: "استعادة",
+    discard: "تجاهل",
+    add_date: "إضافة تاريخ",
+    add_combined: "إضافة نص مركب",
+    date_format: "صيغة التاريخ",
+    date_column: "عمود التاريخ",
+    today: "اليوم",
+    generate_selected: "إنشاء المحدد",
+    attachment_name: "اسم المرفق",
+    email_body: "نص الرسالة",
+    test_connection: "اختبار الاتصال",
+    first_row_is_data: "الصف الأول بيانات",
+    overflows: "نصوص تجاوزت حدودها",
+    shortened_names: "أسماء ملفات مختصرة",
+    retry_failed: "إعادة الفاشلة فقط",
+    file_name: "اسم الملف",
+    status: "الحالة",
+    pending: "قيد الانتظار",
+    sent: "أرسلت",
+    failed: "فشل",
+    pdf_dpi: "دقة ملفات المستندات",
+    output_scale: "مقياس الحفظ",
+    log_level: "مستوى السجل",
+    log_to_file: "حفظ السجل في ملف",
+    export_mapping: "تصدير ربط الحقول",
+    import_mapping: "استيراد ربط الحقول",
+    supersample: "التنعيم",
+    confirm_send: "تأكيد الإرسال",
+    recipients: "المستلمون",
+    email_column: "عمود البريد",
+    from: "من",
+    subject: "الموضوع",
+    send: "إرسال",
+    invalid_date_format: "صيغة التاريخ غير صالحة",
+    qr_code: "رمز استجابة سريعة",
+    qr_data: "بيانات رمز الاستجابة",
+    barcode: "رمز شريطي",
+    barcode_data: "بيانات الرمز الشريطي",
+    image: "صورة",
+    text: "نص",
+    combined: "نص مركب",
+    date: "تاريخ",
+    column: "عمود",
+    template: "القالب",
+    invalid_template: "قالب غير صالح",
+    invalid_image: "صورة غير صالحة",
+    needs_complete_row: "استورد ملف بيانات فيه صف مكتمل واحد على الأقل",
+    needs_two_columns: "يحتاج ملف البيانات إلى عمودين على الأقل لتسمية الشهادات",
+    select_row_to_preview: "اختر صفا لمعاينته",
+    select_row_to_generate: "اختر صفا لإنشاء شهادته",
+    creating: "جار الإنشاء...",
+    sending: "جار الإرسال...",
+    finished: "انتهى!",
+    worker_panicked: "توقف مسار العمل فجأة",
+    no_email_column: "لا يوجد عمود للبريد",
+    saved: "تم الحفظ",
+    open_folder_failed: "تعذر فتح مجلد الحفظ",
+    exported_to: "تم التصدير إلى",
+    mapping_imported: "تم استيراد ربط الحقول",
+    not_in_csv: "غير موجود في هذا الملف",
+    ignored_in_csv: "متجاهل في هذا الملف",
+    connected: "تم الاتصال وتسجيل الدخول",
+    sequential: "تسلسلي",
+    uuid: "معرف فريد",
+    stretch: "تمديد",
+    contain: "احتواء",
+    cover: "تغطية",
+    port: "المنفذ",
+    records: "السجلات",
+    dropped_rows: "صفوف محذوفة لخلايا فارغة",
+    unreadable_rows: "صفوف غير مقروءة",
+    valid_emails: "عناوين بريد صالحة",
+    font_not_installed: "الخط غير مثبت",
+    fallback_glyphs: "ترسم بخط بديل",
+    missing_glyphs: "لا يوجد خط مثبت يعرض",
+    every_certificate_imported: "كل الشهادات تستخدم القالب المستورد",
+    differs_from_layout: "يختلف عن قالب التخطيط، قد تكون الحقول في غير مكانها",
+    column_hint: "أدخل قيمة عمود بكتابة",
+    example: "مثال",
+    fallback_hint: "يرسم عندما تكون قيمة الصف فارغة",
+    baseline_hint: "خط الأساس يضع السطر الأخير على الحافة السفلية",
+    serial_hint: "يضيف عمودا بمعرف فريد لكل صف",
+    preview_widest_hint: "معاينة أطول قيمة من كل عمود معا",
+    generate_selected_hint: "حفظ شهادة الصف المحدد في المكان الذي تختاره",
+    retry_queued_hint: "الرسائل التي فشل إرسالها تبقى حتى ترسل",
+    skip_sent_hint: "عدم الإرسال مجددا للعناوين التي أرسل لها هذا القالب",
+    first_row_hint: "للملفات بدون صف عناوين، يطبق عند الاستيراد التالي",
+    arrow_keys_hint: "مفاتيح الأسهم تتنقل بين الصفوف أيضا",
+    retry_failed_hint: "إنشاء الصفوف التي فشلت أو تجاوزت نصوصها فقط",
+    output_scale_hint: "أصغر لرسائل أخف، وأكبر للطباعة",
+    supersample_hint: "الرسم بحجم أكبر ثم التصغير عند الحفظ لنص أنعم",
+    pdf_dpi_hint: "الدقة التي ترسم بها قوالب المستندات",
+    manifest_hint: "معرف كل شهادة وقيم أعمدتها",
+    in_order_hint: "تكتب الملفات واحدا تلو الآخر بترتيب الصفوف، أبطأ",
+    log_file_hint: "يضيف إلى ملف في مجلد الإعدادات",
+    send_threads_hint: "عدد الرسائل المرسلة في الوقت نفسه، ارفعه إذا سمح خادم البريد",
+    display_name_hint: "يظهر للمستلمين بجانب العنوان",
+    attachment_name_hint: "إذا ترك فارغا يسمى مثل ملف الشهادة",
+    certificates_already_exist: "شهادات موجودة مسبقا",
+};
@@ -1,17 +1,39 @@
+use anyhow::Context;
+use barcoders::sym::{code128::Code128, code39::Code39, ean13::EAN13};
+use calamine::{DataType, Reader, Xlsx};
+use chrono::format::{Fixed, Item, StrftimeItems};
+use chrono::{Datelike, Local, NaiveDate, NaiveTime};
 use csv::StringRecord;
 use eframe::egui::{FontData, FontDefinitions};
 use eframe::epaint::{Color32, FontFamily, Pos2};
-use lettre::message::header::ContentType;
-use lettre::message::{Attachment, MultiPart};
+use i18n::Language;
+use keyring::Entry;
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
+use lettre::message::{Attachment, Mailbox, MultiPart};
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{Message, SmtpTransport, Transport};
+use log::{debug, LevelFilter};
+use qrcode::QrCode;
 use rand::{distributions::Standard, prelude::*};
-use serde::{Deserialize, Serialize};
-use skia_safe::textlayout::{FontCollection, ParagraphBuilder, ParagraphStyle, TextStyle};
-use skia_safe::{icu, Canvas, Data, EncodedImageFormat, FontMgr, Image, Paint, Point, Surface};
-use std::collections::HashMap;
-use std::fs;
-use std::sync::Arc;
+use serde::{Deserialize, Serialize, Serializer};
+use skia_safe::textlayout::{
+    FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, TextStyle,
+};
+use skia_safe::{
+    icu, Canvas, Color, Data, EncodedImageFormat, FilterMode, FontMgr, FontStyle, ISize, Image,
+    Matrix, MipmapMode, Paint, Point, Rect, SamplingOptions, Surface,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, Once};
+use uuid::Uuid;
+
+pub mod i18n;
+pub mod logger;
 
 pub type Record = HashMap<String, String>;
 
@@ -24,32 +46,602 @@ impl Distribution<Wrapper<Color32>> for Standard {
     }
 }
 
-#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+// plain values have to come before tables for toml to serialize it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
+    /// Worker threads used for generating certificates
+    pub threads: usize,
+    /// Emails sent at the same time, kept low so the SMTP server doesn't throttle us
+    pub send_threads: usize,
+    pub output_dir: PathBuf,
+    pub format: OutputFormat,
+    /// Encoding quality of lossy formats, 0-100
+    pub quality: u8,
+    /// RGB fill behind the template, shows through its transparent parts
+    pub background: [u8; 3],
+    /// Resolution PDF templates are rasterized at
+    pub pdf_dpi: u32,
+    /// Certificates are saved at this multiple of the template's resolution
+    pub output_scale: f32,
+    /// Render at this multiple of the output size and downscale on save, 1 turns it off
+    pub supersample: u32,
+    pub log_level: LevelFilter,
+    /// Append the log to `certs.log` in the config directory
+    pub log_to_file: bool,
+    /// Write a `manifest.json` of the generated certificates, for verifying them online
+    pub manifest: bool,
+    /// Generate certificates one at a time in row order instead of in parallel
+    pub in_order: bool,
+    /// Address replies go to instead of the sending account, if set
+    pub reply_to: String,
+    /// Sent in the `Organization` header, if set
+    pub organization: String,
+    /// Name of the attached certificate without extension, `{column}` placeholders are filled
+    /// from the record, the certificate's own file name if empty
+    pub attachment_name: String,
+    /// Plain text of certificate emails, `{column}` placeholders are filled from the record.
+    /// The HTML version is made from it
+    pub email_body: String,
+    pub font_family: String,
+    pub font_size: f32,
+    pub theme: ThemePreference,
+    pub language: Language,
+    pub smtp: Smtp,
     pub email: EmailCreds,
+    /// Text options of column fields by column name, reapplied to CSVs with the same columns.
+    /// Kept last, TOML tables can't be followed by plain values
+    pub field_styles: BTreeMap<String, TextOptions>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            threads: default_threads(),
+            send_threads: 2,
+            output_dir: PathBuf::from("output"),
+            format: OutputFormat::default(),
+            quality: 90,
+            background: [255, 255, 255],
+            pdf_dpi: 300,
+            output_scale: 1.,
+            supersample: 1,
+            log_level: LevelFilter::Info,
+            log_to_file: false,
+            manifest: false,
+            in_order: false,
+            reply_to: String::new(),
+            organization: String::new(),
+            attachment_name: String::new(),
+            email_body: String::from("مرفقة شهادة حضورك."),
+            font_family: String::from("Arial"),
+            font_size: 40.,
+            theme: ThemePreference::default(),
+            language: Language::default(),
+            smtp: Smtp::default(),
+            email: EmailCreds::default(),
+            field_styles: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum ThemePreference {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+pub fn num_cpus() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Every core but one, which is left for the UI
+pub fn default_threads() -> usize {
+    num_cpus().saturating_sub(1).max(1)
 }
 
 #[derive(Default, Clone, Debug, Deserialize, Serialize)]
 pub struct EmailCreds {
     pub username: String,
+    /// Name shown next to the address in the From header
+    #[serde(default)]
+    pub display_name: String,
+    /// Kept in the OS keyring, only read from older config files
+    #[serde(default, skip_serializing)]
     pub password: String,
 }
 
-#[derive(Clone)]
-pub struct TextRect {
-    pub p1: Pos2,
-    pub p2: Pos2,
+const KEYRING_SERVICE: &str = "certs";
+
+impl EmailCreds {
+    /// Reads the password for `username` from the OS keyring
+    pub fn load_password(&mut self) -> anyhow::Result<()> {
+        if self.username.is_empty() {
+            return Ok(());
+        }
+
+        self.password = match Entry::new(KEYRING_SERVICE, &self.username)?.get_password() {
+            Ok(password) => password,
+            Err(keyring::Error::NoEntry) => String::new(),
+            Err(e) => return Err(e).context("Failed to read password from keyring"),
+        };
+
+        Ok(())
+    }
+
+    pub fn store_password(&self) -> anyhow::Result<()> {
+        if self.username.is_empty() {
+            return Ok(());
+        }
+
+        Entry::new(KEYRING_SERVICE, &self.username)?
+            .set_password(&self.password)
+            .context("Failed to save password to keyring")
+    }
+
+    pub fn delete_password(&self) -> anyhow::Result<()> {
+        if self.username.is_empty() {
+            return Ok(());
+        }
+
+        match Entry::new(KEYRING_SERVICE, &self.username)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e).context("Failed to remove password from keyring"),
+        }
+    }
 }
 
-impl Default for TextRect {
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Smtp {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for Smtp {
     fn default() -> Self {
         Self {
-            p1: Pos2::default(),
-            p2: Pos2::default(),
+            host: String::from("smtp.gmail.com"),
+            port: 465,
         }
     }
 }
 
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+        }
+    }
+
+    fn encoded_format(self) -> EncodedImageFormat {
+        match self {
+            OutputFormat::Png => EncodedImageFormat::PNG,
+            OutputFormat::Jpeg => EncodedImageFormat::JPEG,
+            OutputFormat::Webp => EncodedImageFormat::WEBP,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum FitMode {
+    #[default]
+    Stretch,
+    Contain,
+    Cover,
+}
+
+impl FitMode {
+    /// Scale factors that map an image of size `from` onto a page of size `to`
+    pub fn scale(self, from: ISize, to: ISize) -> (f32, f32) {
+        let sx = to.width as f32 / from.width as f32;
+        let sy = to.height as f32 / from.height as f32;
+
+        match self {
+            FitMode::Stretch => (sx, sy),
+            FitMode::Contain => (sx.min(sy), sx.min(sy)),
+            FitMode::Cover => (sx.max(sy), sx.max(sy)),
+        }
+    }
+}
+
+/// Fixed output size, the template (and text placed on it) is scaled onto it using `fit`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct PageSize {
+    pub width: i32,
+    pub height: i32,
+    pub fit: FitMode,
+}
+
+impl Default for PageSize {
+    /// A4 landscape at 300 DPI
+    fn default() -> Self {
+        Self {
+            width: 3508,
+            height: 2480,
+            fit: FitMode::default(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub enum FieldKind {
+    /// Draws the value of the CSV column at this index
+    Column(usize),
+    /// Draws a QR code of the data, `{column}` placeholders are filled from the record
+    QrCode(String),
+    /// Draws a barcode of the data, `{column}` placeholders are filled from the record
+    Barcode(Symbology, String),
+    /// Draws the image loaded from this path, scaled into the field
+    Image(PathBuf),
+    /// Draws the same text on every certificate
+    Static {
+        text: String,
+        font_family: String,
+        font_size: f32,
+    },
+    /// Draws the text with `{column}` placeholders filled from the record, e.g. `{first} {last}`
+    Combined(String),
+    /// Draws today's date, or the date in `column`, formatted with chrono's `format`
+    Date {
+        format: String,
+        column: Option<usize>,
+        locale: Language,
+    },
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum Symbology {
+    #[default]
+    Code128,
+    Code39,
+    Ean13,
+}
+
+impl Symbology {
+    /// Encodes `data` into bars, `1` is a dark bar and `0` is a light one
+    pub fn encode(self, data: &str) -> anyhow::Result<Vec<u8>> {
+        let bars = match self {
+            // start in character-set B, which covers printable ASCII
+            Symbology::Code128 => Code128::new(format!("\u{0181}{data}"))?.encode(),
+            Symbology::Code39 => Code39::new(data)?.encode(),
+            Symbology::Ean13 => EAN13::new(data)?.encode(),
+        };
+
+        Ok(bars)
+    }
+}
+
+const ARABIC_MONTHS: [&str; 12] = [
+    "يناير",
+    "فبراير",
+    "مارس",
+    "أبريل",
+    "مايو",
+    "يونيو",
+    "يوليو",
+    "أغسطس",
+    "سبتمبر",
+    "أكتوبر",
+    "نوفمبر",
+    "ديسمبر",
+];
+
+const ARABIC_WEEKDAYS: [&str; 7] = [
+    "الإثنين",
+    "الثلاثاء",
+    "الأربعاء",
+    "الخميس",
+    "الجمعة",
+    "السبت",
+    "الأحد",
+];
+
+/// Formats `date` like chrono does, with month and weekday names in `locale`
+///
+/// Fails on an unknown specifier or one that needs a time, like `%H`
+pub fn format_date(date: NaiveDate, format: &str, locale: Language) -> anyhow::Result<String> {
+    let month = ARABIC_MONTHS[date.month0() as usize];
+    let weekday = ARABIC_WEEKDAYS[date.weekday().num_days_from_monday() as usize];
+    let items = StrftimeItems::new(format)
+        .map(|item| match (locale, item) {
+            (Language::Arabic, Item::Fixed(Fixed::LongMonthName | Fixed::ShortMonthName)) => {
+                Item::Literal(month)
+            }
+            (Language::Arabic, Item::Fixed(Fixed::LongWeekdayName | Fixed::ShortWeekdayName)) => {
+                Item::Literal(weekday)
+            }
+            (_, item) => item,
+        })
+        .collect::<Vec<_>>();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        anyhow::bail!("Invalid date format {format}");
+    }
+
+    let mut text = String::new();
+    write!(text, "{}", date.format_with_items(items.iter()))
+        .map_err(|_| anyhow::anyhow!("Date format {format} needs a time, not just a date"))?;
+
+    Ok(text)
+}
+
+/// Text of a date field for `record`, `None` if its column is missing or isn't a date, or if the
+/// format is invalid
+fn date_text(
+    format: &str,
+    column: Option<usize>,
+    locale: Language,
+    record: &StringRecord,
+) -> Option<String> {
+    let date = match column {
+        Some(index) => {
+            let value = record.get(index)?.trim();
+            ["%Y-%m-%d", "%d/%m/%Y", "%d-%m-%Y", "%Y/%m/%d"]
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(value, format).ok())?
+        }
+        None => Local::now().date_naive(),
+    };
+
+    // an invalid format is shown in the field editor, the field is left empty until it's fixed
+    format_date(date, format, locale).ok()
+}
+
+/// Name of the virtual column holding generated serials
+pub const SERIAL_COLUMN: &str = "serial";
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum SerialKind {
+    #[default]
+    Sequential,
+    Uuid,
+}
+
+impl SerialKind {
+    /// Serial for the record at `index`
+    pub fn generate(self, index: usize) -> String {
+        match self {
+            SerialKind::Sequential => format!("{:04}", index + 1),
+            SerialKind::Uuid => Uuid::new_v4().to_string(),
+        }
+    }
+}
+
+/// Appends the virtual serial column to `columns` and a generated serial to every record
+pub fn add_serials(kind: SerialKind, columns: &mut StringRecord, records: &mut [StringRecord]) {
+    columns.push_field(SERIAL_COLUMN);
+    for (i, record) in records.iter_mut().enumerate() {
+        record.push_field(&kind.generate(i));
+    }
+}
+
+/// Layout and spacing of a text field
+#[derive(Default, Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+pub struct TextOptions {
+    /// Line height as a multiple of the font size, the font's own if `None`
+    pub line_height: Option<f32>,
+    /// Text past this many lines is cut off with an ellipsis
+    pub max_lines: Option<usize>,
+    /// Values longer than this many characters are truncated with an ellipsis before layout
+    pub max_chars: Option<usize>,
+    /// Extra space between letters, in pixels
+    pub letter_spacing: f32,
+    /// Extra space between words, in pixels
+    pub word_spacing: f32,
+    /// Where the text sits inside the height of its rectangle
+    pub vertical_align: VerticalAlign,
+    /// Space kept clear between the text and the edges of its rectangle, in pixels
+    pub padding: f32,
+    /// Point of the text placed on the rectangle, `vertical_align` only applies to `Top`
+    #[serde(default)]
+    pub anchor: TextAnchor,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum VerticalAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum TextAnchor {
+    /// The top of the text on the top edge of the rectangle, moved down by the vertical alignment.
+    /// Lines are right aligned
+    #[default]
+    Top,
+    /// The middle of the text on the middle of the rectangle, lines are centered
+    Center,
+    /// The last line's baseline on the bottom edge of the rectangle, descenders hang below it.
+    /// Lines are right aligned
+    Baseline,
+}
+
+#[derive(Clone)]
+pub struct Field {
+    pub rect: TextRect,
+    pub color: Color32,
+    pub kind: FieldKind,
+    /// Whether the field is drawn, independent of where its rectangle is
+    pub enabled: bool,
+    pub text: TextOptions,
+    /// Drawn instead of an empty value, only used by column fields
+    pub fallback: String,
+}
+
+/// A `Field` as written to disk
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SavedField {
+    pub rect: [f32; 4],
+    pub color: [u8; 4],
+    pub kind: FieldKind,
+    pub enabled: bool,
+    pub text: TextOptions,
+    #[serde(default)]
+    pub fallback: String,
+}
+
+impl From<&Field> for SavedField {
+    fn from(field: &Field) -> Self {
+        Self {
+            rect: [
+                field.rect.p1.x,
+                field.rect.p1.y,
+                field.rect.p2.x,
+                field.rect.p2.y,
+            ],
+            color: field.color.to_array(),
+            kind: field.kind.clone(),
+            enabled: field.enabled,
+            text: field.text,
+            fallback: field.fallback.clone(),
+        }
+    }
+}
+
+impl From<SavedField> for Field {
+    fn from(field: SavedField) -> Self {
+        let [x1, y1, x2, y2] = field.rect;
+        let [r, g, b, a] = field.color;
+        Self {
+            rect: TextRect {
+                p1: Pos2::new(x1, y1),
+                p2: Pos2::new(x2, y2),
+            },
+            color: Color32::from_rgba_premultiplied(r, g, b, a),
+            kind: field.kind,
+            enabled: field.enabled,
+            text: field.text,
+            fallback: field.fallback,
+        }
+    }
+}
+
+/// Fields placed in the layout window, with the files they were placed on
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
+pub struct Layout {
+    pub csv: Option<PathBuf>,
+    pub template: Option<PathBuf>,
+    pub font_size: f32,
+    pub fields: Vec<SavedField>,
+}
+
+impl Layout {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let layout = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read layout {}", path.display()))?;
+        serde_json::from_str(&layout).context("Invalid layout file")
+    }
+}
+
+/// Field setup that carries over to another CSV, columns are referenced by name instead of index
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FieldMapping {
+    pub font_family: String,
+    pub font_size: f32,
+    pub fields: Vec<MappedField>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MappedField {
+    /// Column the field draws or reads its date from
+    pub column: Option<String>,
+    pub field: SavedField,
+}
+
+impl FieldMapping {
+    pub fn new(
+        columns: &StringRecord,
+        fields: &[Field],
+        font_family: &str,
+        font_size: f32,
+    ) -> Self {
+        let fields = fields
+            .iter()
+            .map(|field| {
+                let index = match field.kind {
+                    FieldKind::Column(i) => Some(i),
+                    FieldKind::Date { column, .. } => column,
+                    _ => None,
+                };
+                MappedField {
+                    column: index.and_then(|i| columns.get(i)).map(String::from),
+                    field: field.into(),
+                }
+            })
+            .collect();
+
+        Self {
+            font_family: font_family.to_string(),
+            font_size,
+            fields,
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mapping = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read mapping {}", path.display()))?;
+        serde_json::from_str(&mapping).context("Invalid mapping file")
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mapping = serde_json::to_string_pretty(self)?;
+        fs::write(path, mapping).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Fields re-pointed at `columns` by name, and the column names `columns` doesn't have
+    pub fn fields_for(self, columns: &StringRecord) -> (Vec<Field>, Vec<String>) {
+        let mut missing = Vec::new();
+        let mut fields = Vec::new();
+        for MappedField { column, field } in self.fields {
+            let index = column
+                .as_ref()
+                .and_then(|name| columns.iter().position(|column| column == name));
+            if let (Some(name), None) = (&column, index) {
+                missing.push(name.clone());
+            }
+            let mut field = Field::from(field);
+            match &mut field.kind {
+                FieldKind::Column(i) => match index {
+                    Some(index) => *i = index,
+                    None => continue,
+                },
+                FieldKind::Date { column, .. } => *column = index,
+                _ => {}
+            }
+            fields.push(field);
+        }
+
+        (fields, missing)
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct TextRect {
+    pub p1: Pos2,
+    pub p2: Pos2,
+}
+
 impl TextRect {
     pub fn min(&self) -> Self {
         Self {
@@ -59,121 +651,1010 @@ impl TextRect {
     }
 }
 
-pub fn send_email(email_creds: EmailCreds, filename: &str, to: &str) -> anyhow::Result<()> {
-    let attachment = Attachment::new(String::from("Certificate.png")).body(
-        fs::read(format!("output/{}", filename)).expect("Read file"),
-        ContentType::parse("image/png").expect("Failed to get MIME Type"),
+#[derive(Clone)]
+struct Organization(String);
+
+impl Header for Organization {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Organization")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// Name `record`'s certificate is attached to its email as
+pub fn attachment_name(
+    config: &Config,
+    columns: &StringRecord,
+    record: &StringRecord,
+    filename: &str,
+) -> String {
+    if config.attachment_name.is_empty() {
+        return Path::new(filename)
+            .file_name()
+            .map_or(filename.to_string(), |name| {
+                name.to_string_lossy().to_string()
+            });
+    }
+
+    let name = fill_template(&config.attachment_name, columns, record);
+    format!("{}.{}", name.trim(), config.format.extension())
+}
+
+/// Text of `record`'s email, from the configured body
+pub fn email_body(config: &Config, columns: &StringRecord, record: &StringRecord) -> String {
+    fill_template(&config.email_body, columns, record)
+        .trim()
+        .to_string()
+}
+
+/// HTML version of a plain text email body, right to left text stays right to left
+#[cfg(not(feature = "baba"))]
+fn body_html(body: &str) -> String {
+    let escaped = body
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>\n");
+    format!("<div dir=\"auto\">{escaped}</div>")
+}
+
+/// Subject line of certificate emails
+#[cfg(feature = "baba")]
+pub const EMAIL_SUBJECT: &str = include_str!("../baba-subject.txt");
+#[cfg(not(feature = "baba"))]
+pub const EMAIL_SUBJECT: &str = "شهادة حضور";
+
+pub fn send_email(
+    config: &Config,
+    file: &Path,
+    attachment_name: &str,
+    body: &str,
+    to: &str,
+) -> anyhow::Result<()> {
+    let certificate =
+        fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let attachment = Attachment::new(attachment_name.to_string()).body(
+        certificate,
+        ContentType::parse(config.format.mime()).expect("Failed to get MIME Type"),
     );
 
-    #[cfg(feature = "baba")]
-    let html = include_str!("../baba.html");
+    let display_name = &config.email.display_name;
+    let from = Mailbox::new(
+        (!display_name.is_empty()).then(|| display_name.clone()),
+        config.email.username.parse()?,
+    );
 
-    #[cfg(feature = "baba")]
-    let subject = include_str!("../baba-subject.txt");
+    let mut builder = Message::builder().from(from).to(to
+        .parse()
+        .with_context(|| format!("Invalid recipient {to}"))?);
+    if !config.reply_to.is_empty() {
+        builder = builder.reply_to(config.reply_to.parse()?);
+    }
+    if !config.organization.is_empty() {
+        builder = builder.header(Organization(config.organization.clone()));
+    }
 
     #[cfg(feature = "baba")]
-    let email = Message::builder()
-        .from(email_creds.username.parse().unwrap())
-        .to(to.parse().unwrap())
-        .subject(subject)
-        .multipart(
-            MultiPart::alternative().multipart(
-                MultiPart::mixed().singlepart(attachment).singlepart(
-                    SinglePart::builder()
-                        .header(ContentType::TEXT_HTML)
-                        .body(String::from(html)),
-                ),
-            ),
-        )
-        .expect("Email");
+    let html = String::from(include_str!("../baba.html"));
 
     #[cfg(not(feature = "baba"))]
-    let email = Message::builder()
-        .from(email_creds.username.parse().unwrap())
-        .to(to.parse().unwrap())
-        .subject("شهادة حضور")
-        .multipart(MultiPart::alternative().multipart(MultiPart::mixed().singlepart(attachment)))
-        .expect("Email");
+    let html = body_html(body);
 
-    let creds = Credentials::new(email_creds.username, email_creds.password);
+    // clients show whichever body they can render, with the certificate attached next to it
+    let email = builder.subject(EMAIL_SUBJECT).multipart(
+        MultiPart::mixed()
+            .multipart(MultiPart::alternative_plain_html(body.to_string(), html))
+            .singlepart(attachment),
+    )?;
 
-    let mailer = SmtpTransport::relay("smtp.gmail.com")
-        .unwrap()
+    mailer(config)?.send(&email)?;
+
+    Ok(())
+}
+
+fn mailer(config: &Config) -> anyhow::Result<SmtpTransport> {
+    let creds = Credentials::new(config.email.username.clone(), config.email.password.clone());
+
+    Ok(SmtpTransport::relay(&config.smtp.host)?
+        .port(config.smtp.port)
         .credentials(creds)
-        .build();
+        .build())
+}
 
-    mailer.send(&email)?;
+/// Connects and logs in to the SMTP server without sending anything
+pub fn test_connection(config: &Config) -> anyhow::Result<()> {
+    let connected = mailer(config)?
+        .test_connection()
+        .with_context(|| format!("Failed to connect to {}", config.smtp.host))?;
+    anyhow::ensure!(connected, "{} refused the connection", config.smtp.host);
 
     Ok(())
 }
 
+/// `address` as compared between rows, mail servers ignore case and spreadsheets add stray spaces
+pub fn normalize_address(address: &str) -> String {
+    address.trim().to_lowercase()
+}
+
+/// Addresses a batch was already sent to, one per line, so a failed batch can be resumed
+pub struct SentLog {
+    file: Mutex<File>,
+    sent: HashSet<String>,
+}
+
+impl SentLog {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create sent log directory")?;
+        }
+        let sent = match fs::read_to_string(path) {
+            Ok(log) => log.lines().map(normalize_address).collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e).context("Failed to read sent log"),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+            sent,
+        })
+    }
+
+    pub fn contains(&self, address: &str) -> bool {
+        self.sent.contains(&normalize_address(address))
+    }
+
+    pub fn add(&self, address: &str) -> anyhow::Result<()> {
+        let mut file = self.file.lock().expect("sent log lock");
+        writeln!(file, "{}", normalize_address(address)).context("Failed to write sent log")
+    }
+}
+
+/// An email that failed to send, with everything needed to send it again later
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QueuedEmail {
+    pub to: String,
+    /// The generated certificate, absolute so the output directory can change in between
+    pub file: PathBuf,
+    pub attachment_name: String,
+    pub body: String,
+    /// Sent log of the batch the email belongs to, the address is added once it goes out
+    pub sent_log: PathBuf,
+    /// Why the last attempt failed
+    pub error: String,
+}
+
+/// Emails waiting to be retried, kept on disk so a dropped connection doesn't lose them
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct EmailQueue {
+    pub emails: Vec<QueuedEmail>,
+}
+
+impl EmailQueue {
+    /// Reads the queue at `path`, empty if nothing was queued yet
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(queue) => serde_json::from_str(&queue).context("Invalid email queue"),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context("Failed to read email queue"),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create email queue directory")?;
+        }
+        let queue = serde_json::to_string_pretty(self)?;
+        fs::write(path, queue).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+pub fn decode_template(template: &[u8]) -> anyhow::Result<Image> {
+    let data = Data::new_copy(template);
+    let image = Image::from_encoded(data)
+        .context("Template is not a supported image, use PNG, JPEG or WebP")?;
+
+    if image.dimensions().is_empty() {
+        anyhow::bail!("Template image is empty");
+    }
+
+    Ok(image)
+}
+
+/// Reads the horizontal and vertical DPI from PNG `pHYs` or JPEG `JFIF` metadata, if present
+pub fn template_dpi(template: &[u8]) -> Option<(f32, f32)> {
+    if let Some(mut chunks) = template.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+        while chunks.len() >= 12 {
+            let len = u32::from_be_bytes(chunks[..4].try_into().ok()?) as usize;
+            let data = chunks.get(8..8 + len)?;
+            match &chunks[4..8] {
+                b"pHYs" if len == 9 && data[8] == 1 => {
+                    let x = u32::from_be_bytes(data[..4].try_into().ok()?);
+                    let y = u32::from_be_bytes(data[4..8].try_into().ok()?);
+                    return Some((x as f32 * 0.0254, y as f32 * 0.0254));
+                }
+                b"IDAT" | b"IEND" => return None,
+                _ => chunks = chunks.get(12 + len..)?,
+            }
+        }
+    } else if let Some(mut segments) = template.strip_prefix(b"\xff\xd8") {
+        while segments.len() >= 4 && segments[0] == 0xff {
+            let len = u16::from_be_bytes([segments[2], segments[3]]) as usize;
+            let data = segments.get(4..2 + len)?;
+            match segments[1] {
+                0xe0 if data.len() >= 12 && data.starts_with(b"JFIF\0") => {
+                    let x = u16::from_be_bytes([data[8], data[9]]) as f32;
+                    let y = u16::from_be_bytes([data[10], data[11]]) as f32;
+                    return match data[7] {
+                        1 => Some((x, y)),
+                        2 => Some((x * 2.54, y * 2.54)),
+                        _ => None,
+                    };
+                }
+                0xda => return None,
+                _ => segments = segments.get(2 + len..)?,
+            }
+        }
+    }
+
+    None
+}
+
+/// Writes `manifest.json` into `dir`, keyed by serial when there is one and by filename otherwise.
+/// Fails without writing it if two certificates share a key
+pub fn write_manifest(
+    dir: &Path,
+    columns: &StringRecord,
+    certificates: &[(&StringRecord, &str)],
+) -> anyhow::Result<()> {
+    let serial = columns.iter().position(|column| column == SERIAL_COLUMN);
+    let manifest = certificates
+        .iter()
+        .map(|(record, filename)| {
+            let mut entry = serde_json::Map::new();
+            entry.insert(String::from("file"), (*filename).into());
+            for (column, value) in columns.iter().zip(record.iter()) {
+                entry.insert(column.to_string(), value.into());
+            }
+            let id = serial.map_or(*filename, |i| &record[i]);
+            (id.to_string(), serde_json::Value::Object(entry))
+        })
+        .collect::<Vec<_>>();
+
+    let mut ids = HashSet::new();
+    let duplicates = manifest
+        .iter()
+        .filter(|(id, _)| !ids.insert(id))
+        .map(|(id, _)| id.as_str())
+        .collect::<BTreeSet<_>>();
+    if !duplicates.is_empty() {
+        let duplicates = duplicates.into_iter().collect::<Vec<_>>().join(", ");
+        anyhow::bail!("Manifest not written, more than one certificate has the key {duplicates}");
+    }
+
+    fs::create_dir_all(dir).context("Failed to create output directory")?;
+    let path = dir.join("manifest.json");
+    let manifest = serde_json::to_string_pretty(&InOrder(manifest))?;
+    fs::write(&path, manifest).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Serializes as a JSON object whose keys keep the order of the entries, unlike `serde_json::Map`
+struct InOrder(Vec<(String, serde_json::Value)>);
+
+impl Serialize for InOrder {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+    }
+}
+
+/// Text of a workbook cell, dates are written like `2024-03-05` instead of Excel's day count
+fn cell_text(cell: &DataType) -> String {
+    match (cell, cell.as_datetime()) {
+        (DataType::DateTime(_), Some(datetime)) if datetime.time() == NaiveTime::MIN => {
+            datetime.format("%Y-%m-%d").to_string()
+        }
+        (DataType::DateTime(_), Some(datetime)) => datetime.format("%Y-%m-%d %H:%M").to_string(),
+        _ => cell.to_string(),
+    }
+}
+
+/// Reads the header row and records of the first sheet of an Excel workbook, without a header row
+/// the columns are numbered after `column`
+pub fn read_xlsx(
+    file: &[u8],
+    has_headers: bool,
+    column: &str,
+) -> anyhow::Result<(StringRecord, Vec<StringRecord>)> {
+    let mut workbook = Xlsx::new(Cursor::new(file)).context("Not a valid Excel workbook")?;
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .context("Workbook has no sheets")??;
+    let mut rows = sheet
+        .rows()
+        .map(|row| row.iter().map(cell_text).collect::<StringRecord>());
+    if !has_headers {
+        let rows = rows.collect::<Vec<_>>();
+        let columns = numbered_columns(rows.first().context("First sheet is empty")?.len(), column);
+        return Ok((columns, rows));
+    }
+    let columns = rows.next().context("First sheet is empty")?;
+
+    Ok((columns, rows.collect()))
+}
+
+/// Reads a template image, rasterizing the first page of PDFs at `pdf_dpi`. PDFs need `pdftoppm`
+/// from poppler on the `PATH`, and take a while at high resolutions so call this off the UI thread
+pub fn read_template(path: &Path, pdf_dpi: u32) -> anyhow::Result<Vec<u8>> {
+    let is_pdf = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"));
+    if !is_pdf {
+        return fs::read(path).with_context(|| format!("Failed to read {}", path.display()));
+    }
+
+    // pdftoppm from poppler does the rendering, it ships with most Linux distros and has to be
+    // installed separately on Windows and macOS
+    let output = std::env::temp_dir().join(format!("certs-{}", Uuid::new_v4()));
+    let status = Command::new("pdftoppm")
+        .args(["-png", "-singlefile", "-f", "1", "-l", "1", "-r"])
+        .arg(pdf_dpi.to_string())
+        .arg(path)
+        .arg(&output)
+        .status()
+        .context("Failed to run pdftoppm, install poppler to use PDF templates")?;
+    anyhow::ensure!(
+        status.success(),
+        "pdftoppm failed to render {}",
+        path.display()
+    );
+
+    let png = output.with_extension("png");
+    let image = fs::read(&png).context("Failed to read rendered PDF page");
+    let _ = fs::remove_file(&png);
+
+    image
+}
+
+/// Stand-in column names for data without a header row, `column` followed by its number
+pub fn numbered_columns(len: usize, column: &str) -> StringRecord {
+    (1..=len).map(|i| format!("{column} {i}")).collect()
+}
+
+/// Longest file or folder name most file systems accept
+const MAX_NAME_LEN: usize = 255;
+
+/// Longest full path the OS accepts, less the terminating NUL. Windows paths without the `\\?\`
+/// prefix stop at 260
+#[cfg(windows)]
+const MAX_PATH_LEN: usize = 259;
+#[cfg(target_os = "macos")]
+const MAX_PATH_LEN: usize = 1023;
+#[cfg(not(any(windows, target_os = "macos")))]
+const MAX_PATH_LEN: usize = 4095;
+
+/// Length of `text` the way the OS counts it against its limits, UTF-16 units on Windows and
+/// bytes elsewhere
+fn os_len(text: &str) -> usize {
+    if cfg!(windows) {
+        text.encode_utf16().count()
+    } else {
+        text.len()
+    }
+}
+
+/// `stem` with its extension, cut short if it's longer than `max_len` or than file systems allow,
+/// and `None` if it fits. A hash of the whole stem replaces the cut part so names that only differ
+/// there stay apart
+fn shorten_name(stem: &str, extension: Option<&str>, max_len: usize) -> Option<String> {
+    let extension = extension.map_or(String::new(), |extension| format!(".{extension}"));
+    let max_len = max_len.min(MAX_NAME_LEN);
+    if os_len(stem) + os_len(&extension) <= max_len {
+        return None;
+    }
+
+    // FNV-1a, stable across runs so reruns find the files they wrote before
+    let hash = stem.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    let suffix = format!("~{hash:08x}{extension}");
+    let budget = max_len.saturating_sub(os_len(&suffix));
+    let end = stem
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take_while(|&end| os_len(&stem[..end]) <= budget)
+        .last()
+        .unwrap_or(0);
+
+    Some(format!("{}{suffix}", &stem[..end]))
+}
+
+/// Path of a certificate relative to `dir`, `folder`/`stem`.`extension` or just the file name,
+/// with the parts shortened so each name and the whole path stay within the OS limits. Also
+/// returns whether anything had to be shortened
+pub fn fit_file_name(
+    dir: &Path,
+    folder: Option<&str>,
+    stem: &str,
+    extension: &str,
+) -> (String, bool) {
+    let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+    // the separator after `dir` counts too
+    let available = MAX_PATH_LEN.saturating_sub(os_len(&dir.to_string_lossy()) + 1);
+    let name_len = os_len(stem) + 1 + os_len(extension);
+
+    let Some(folder) = folder else {
+        let name = shorten_name(stem, Some(extension), available);
+        let shortened = name.is_some();
+        return (
+            name.unwrap_or_else(|| format!("{stem}.{extension}")),
+            shortened,
+        );
+    };
+    // a long folder name gives up to half of the room, the file name gets the rest
+    let folder_len = if os_len(folder) + 1 + name_len <= available {
+        os_len(folder)
+    } else {
+        os_len(folder).min(available / 2)
+    };
+    let folder_name = shorten_name(folder, None, folder_len);
+    let name = shorten_name(
+        stem,
+        Some(extension),
+        available.saturating_sub(os_len(folder_name.as_deref().unwrap_or(folder)) + 1),
+    );
+    let shortened = folder_name.is_some() || name.is_some();
+
+    (
+        format!(
+            "{}/{}",
+            folder_name.as_deref().unwrap_or(folder),
+            name.unwrap_or_else(|| format!("{stem}.{extension}"))
+        ),
+        shortened,
+    )
+}
+
+/// Replaces every `{column}` in `template` with that column's value in `record`
+pub fn fill_template(template: &str, columns: &StringRecord, record: &StringRecord) -> String {
+    columns
+        .iter()
+        .zip(record.iter())
+        .fold(template.to_string(), |text, (column, value)| {
+            text.replace(&format!("{{{column}}}"), value)
+        })
+}
+
+/// Everything shared by the certificates of one batch
+#[derive(Clone)]
+pub struct Batch {
+    pub columns: StringRecord,
+    /// Enabled fields and where they go, in template pixels
+    pub fields: Vec<(FieldKind, Rect, TextOptions)>,
+    /// Text drawn by column fields for records with an empty value, keyed by column
+    pub fallbacks: HashMap<usize, String>,
+    pub template: Image,
+    /// Column whose value picks one of `templates` instead of `template`
+    pub template_column: Option<usize>,
+    pub templates: HashMap<String, Image>,
+    /// Decoded images of `FieldKind::Image` fields
+    pub images: HashMap<PathBuf, Image>,
+    pub font_family: String,
+    pub font_size: f32,
+    pub page: Option<PageSize>,
+    pub output_dir: PathBuf,
+    pub format: OutputFormat,
+    pub quality: u8,
+    pub background: Color,
+    /// Output resolution relative to the template, text and fields scale along
+    pub scale: f32,
+    /// Renders at this multiple of the output size and shrinks back down, for crisper text
+    pub supersample: u32,
+}
+
+impl Batch {
+    /// `record`'s value of `column`, or the column's fallback if the value is empty
+    fn column_text<'a>(&'a self, record: &'a StringRecord, column: usize) -> Option<&'a str> {
+        match (record.get(column), self.fallbacks.get(&column)) {
+            (Some(value), Some(fallback)) if value.trim().is_empty() => Some(fallback),
+            (None, fallback) => fallback.map(String::as_str),
+            (value, _) => value,
+        }
+    }
+
+    /// Background for `record`, falling back to the default template
+    pub fn template(&self, record: &StringRecord) -> &Image {
+        self.template_column
+            .and_then(|index| record.get(index))
+            .and_then(|value| self.templates.get(value))
+            .unwrap_or(&self.template)
+    }
+}
+
+pub struct Certificate {
+    pub image: Image,
+    /// Text fields that didn't fit their rectangle and got cut off
+    pub overflows: Vec<String>,
+}
+
+/// Renders and saves `record`'s certificate, returning the fields that overflowed.
+/// Use `render_certificate` and `encode_certificate` to keep it in memory instead
 pub fn generate_certificate(
+    batch: &Batch,
     record: &StringRecord,
-    points: Vec<(Point, f32)>,
-    template: Arc<Vec<u8>>,
     filename: &str,
-    font_size: f32,
-) {
-    let data = Data::new_copy(&template);
-    let image = Image::from_encoded(data).unwrap();
-    let mut surface = Surface::new_raster_n32_premul(image.dimensions()).unwrap();
-    let mut canvas = surface.canvas();
-    canvas.draw_image(image, Point::new(0., 0.), Some(&Paint::default()));
-    for (field, point) in record.iter().zip(points) {
-        if point.0.is_zero() {
-            println!("skipping {field}");
-            continue;
+) -> anyhow::Result<Vec<String>> {
+    let certificate = render_certificate(batch, record)?;
+    save_certificate(&certificate.image, batch, filename)?;
+    debug!("saved {filename}");
+
+    Ok(certificate.overflows)
+}
+
+/// Draws the certificate of `record` without saving it
+pub fn render_certificate(batch: &Batch, record: &StringRecord) -> anyhow::Result<Certificate> {
+    let template = batch.template(record);
+    let dimensions = batch.page.map_or(template.dimensions(), |page| {
+        ISize::new(page.width, page.height)
+    });
+    let output = ISize::new(
+        ((dimensions.width as f32 * batch.scale).round() as i32).max(1),
+        ((dimensions.height as f32 * batch.scale).round() as i32).max(1),
+    );
+    let supersample = batch.supersample.max(1);
+    let mut surface = Surface::new_raster_n32_premul((
+        output.width * supersample as i32,
+        output.height * supersample as i32,
+    ))
+    .context("Failed to create surface for template")?;
+    let canvas = surface.canvas();
+    canvas.clear(batch.background);
+    let scale = batch.scale * supersample as f32;
+    canvas.scale((scale, scale));
+    let (fit, uniform) = match batch.page {
+        Some(page) => {
+            let (sx, sy) = page.fit.scale(template.dimensions(), dimensions);
+            let mut fit = Matrix::translate(Point::new(
+                (dimensions.width as f32 - template.width() as f32 * sx) / 2.,
+                (dimensions.height as f32 - template.height() as f32 * sy) / 2.,
+            ));
+            fit.pre_scale((sx, sy), None);
+            (fit, sx.min(sy))
         }
+        None => (Matrix::new_identity(), 1.),
+    };
+    canvas.save();
+    canvas.concat(&fit);
+    canvas.draw_image(template, Point::new(0., 0.), Some(&Paint::default()));
+    canvas.restore();
+    // Fields only get the uniform part of the fit so stretching a template doesn't squash text,
+    // their rects are moved through the full fit instead
+    canvas.scale((uniform, uniform));
+    let mut fields = fit;
+    fields.post_scale((1. / uniform, 1. / uniform), None);
+    let font_collection = font_collection();
+    let mut overflows = Vec::new();
+    for (kind, rect, options) in &batch.fields {
+        let (rect, _) = fields.map_rect(rect);
+        match kind {
+            FieldKind::Column(index) => {
+                let Some(text) = batch.column_text(record, *index) else {
+                    continue;
+                };
+                if draw_text(
+                    canvas,
+                    &font_collection,
+                    text,
+                    rect,
+                    &batch.font_family,
+                    batch.font_size,
+                    options,
+                ) {
+                    overflows.push(batch.columns.get(*index).unwrap_or_default().to_string());
+                }
+            }
+            FieldKind::QrCode(data) => {
+                let data = fill_template(data, &batch.columns, record);
+                draw_qr_code(canvas, &data, rect)?;
+            }
+            FieldKind::Barcode(symbology, data) => {
+                let data = fill_template(data, &batch.columns, record);
+                draw_barcode(canvas, *symbology, &data, rect)?;
+            }
+            FieldKind::Image(path) => {
+                let image = batch
+                    .images
+                    .get(path)
+                    .with_context(|| format!("Image {} is not loaded", path.display()))?;
+                canvas.draw_image_rect(image, None, rect, &Paint::default());
+            }
+            FieldKind::Static {
+                text,
+                font_family,
+                font_size,
+            } => {
+                if draw_text(
+                    canvas,
+                    &font_collection,
+                    text,
+                    rect,
+                    font_family,
+                    *font_size,
+                    options,
+                ) {
+                    overflows.push(text.clone());
+                }
+            }
+            FieldKind::Combined(template) => {
+                let text = fill_template(template, &batch.columns, record);
+                if draw_text(
+                    canvas,
+                    &font_collection,
+                    text.trim(),
+                    rect,
+                    &batch.font_family,
+                    batch.font_size,
+                    options,
+                ) {
+                    overflows.push(template.clone());
+                }
+            }
+            FieldKind::Date {
+                format,
+                column,
+                locale,
+            } => {
+                let Some(text) = date_text(format, *column, *locale, record) else {
+                    continue;
+                };
+                if draw_text(
+                    canvas,
+                    &font_collection,
+                    &text,
+                    rect,
+                    &batch.font_family,
+                    batch.font_size,
+                    options,
+                ) {
+                    overflows.push(String::from("date"));
+                }
+            }
+        }
+    }
 
-        let width = point.1;
-        draw_text(&mut canvas, field, point.0, width, font_size);
+    let mut image = surface.image_snapshot();
+    if supersample > 1 {
+        image = downscale(&image, output)?;
     }
-    save_as(&mut surface, &filename);
-    println!("saved!");
+
+    Ok(Certificate { image, overflows })
 }
 
-fn draw_text(canvas: &mut Canvas, text: &str, position: Point, width: f32, font_size: f32) {
-    icu::init();
+/// Shrinks a supersampled render to its output size, averaging the extra pixels into smoother edges
+fn downscale(image: &Image, size: ISize) -> anyhow::Result<Image> {
+    let mut surface =
+        Surface::new_raster_n32_premul(size).context("Failed to create output surface")?;
+    surface.canvas().draw_image_rect_with_sampling_options(
+        image,
+        None,
+        Rect::from_wh(size.width as f32, size.height as f32),
+        SamplingOptions::new(FilterMode::Linear, MipmapMode::Linear),
+        &Paint::default(),
+    );
+
+    Ok(surface.image_snapshot())
+}
+
+static ICU: Once = Once::new();
+
+thread_local! {
+    // FontCollection can't be shared between threads, so each worker builds one and reuses it
+    static FONT_COLLECTION: FontCollection = {
+        let mut font_collection = FontCollection::new();
+        font_collection.set_default_font_manager(FontMgr::new(), None);
+        font_collection
+    };
+}
+
+fn font_collection() -> FontCollection {
+    ICU.call_once(icu::init);
+    FONT_COLLECTION.with(FontCollection::clone)
+}
+
+fn draw_text(
+    canvas: &mut Canvas,
+    font_collection: &FontCollection,
+    text: &str,
+    rect: Rect,
+    font_family: &str,
+    font_size: f32,
+    options: &TextOptions,
+) -> bool {
+    let truncated;
+    let text = match options.max_chars {
+        Some(max_chars) if text.chars().count() > max_chars => {
+            truncated = format!("{}…", text.chars().take(max_chars).collect::<String>());
+            truncated.as_str()
+        }
+        _ => text,
+    };
+    let rect = rect.with_inset((
+        options.padding.min(rect.width() / 2.),
+        options.padding.min(rect.height() / 2.),
+    ));
+    let mut paragraph = paragraph(font_collection, text, font_family, font_size, options);
+    paragraph.layout(rect.width());
+    let overflows = paragraph.longest_line() > rect.width()
+        || paragraph.height() > rect.height()
+        || paragraph.did_exceed_max_lines();
+
+    // drop the lines that don't fit in the rectangle, ending the last one that does with an ellipsis
+    let lines = paragraph.line_number();
+    if lines > 1 {
+        let line_height = paragraph.height() / lines as f32;
+        let fitting = ((rect.height() / line_height) as usize).max(1);
+        if fitting < lines {
+            let options = TextOptions {
+                max_lines: Some(options.max_lines.map_or(fitting, |max| max.min(fitting))),
+                ..*options
+            };
+            paragraph = self::paragraph(font_collection, text, font_family, font_size, &options);
+            paragraph.layout(rect.width());
+        }
+    }
+
+    let free = (rect.height() - paragraph.height()).max(0.);
+    let top = match (options.anchor, options.vertical_align) {
+        (TextAnchor::Top, VerticalAlign::Top) => rect.top,
+        (TextAnchor::Top, VerticalAlign::Middle) => rect.top + free / 2.,
+        (TextAnchor::Top, VerticalAlign::Bottom) => rect.top + free,
+        (TextAnchor::Center, _) => rect.center_y() - paragraph.height() / 2.,
+        (TextAnchor::Baseline, _) => {
+            let lines = paragraph.line_number().max(1) as f32;
+            let line_height = paragraph.height() / lines;
+            rect.bottom - paragraph.alphabetic_baseline() - line_height * (lines - 1.)
+        }
+    };
+    paragraph.paint(canvas, Point::new(rect.left, top));
 
-    let mut font_collection = FontCollection::new();
-    font_collection.set_default_font_manager(FontMgr::new(), None);
+    overflows
+}
 
+fn paragraph(
+    font_collection: &FontCollection,
+    text: &str,
+    font_family: &str,
+    font_size: f32,
+    options: &TextOptions,
+) -> Paragraph {
     let mut paragraph_style = ParagraphStyle::new();
-    paragraph_style.set_text_align(skia_safe::textlayout::TextAlign::Right);
+    paragraph_style.set_text_align(match options.anchor {
+        TextAnchor::Center => skia_safe::textlayout::TextAlign::Center,
+        _ => skia_safe::textlayout::TextAlign::Right,
+    });
     // paragraph_style.set_text_direction(skia_safe::textlayout::TextDirection::RTL);
+    if let Some(max_lines) = options.max_lines {
+        paragraph_style.set_max_lines(max_lines).set_ellipsis("…");
+    }
 
     let mut text_style = TextStyle::new();
     text_style
-        .set_font_families(&["Arial"])
+        .set_font_families(&[font_family])
         .set_font_size(font_size)
+        .set_letter_spacing(options.letter_spacing)
+        .set_word_spacing(options.word_spacing)
         .set_foreground_color(Paint::default());
+    if let Some(line_height) = options.line_height {
+        text_style.set_height(line_height).set_height_override(true);
+    }
 
-    let mut paragraph_builder = ParagraphBuilder::new(&paragraph_style, font_collection);
+    let mut paragraph_builder = ParagraphBuilder::new(&paragraph_style, font_collection.clone());
     paragraph_builder.push_style(&text_style).add_text(text);
-    let mut paragraph = paragraph_builder.build();
-    paragraph.layout(width);
-    paragraph.paint(canvas, position);
+    paragraph_builder.build()
 }
 
-fn save_as(surface: &mut Surface, filename: &str) {
-    let image = surface.image_snapshot();
-    let data = image.encode_to_data(EncodedImageFormat::PNG).unwrap();
-    match fs::create_dir_all("output") {
-        Err(e) => match e.kind() {
-            std::io::ErrorKind::AlreadyExists => {
-                println!("dir already exists: {}", e);
+/// Record made of the widest value of every column, to check that nothing overflows its field
+pub fn widest_record(batch: &Batch, records: &[StringRecord]) -> StringRecord {
+    let font_collection = font_collection();
+    (0..batch.columns.len())
+        .map(|i| {
+            let options = batch
+                .fields
+                .iter()
+                .find(|(kind, ..)| *kind == FieldKind::Column(i))
+                .map_or(TextOptions::default(), |(.., options)| *options);
+            records
+                .iter()
+                .filter_map(|record| record.get(i))
+                .map(|value| {
+                    let mut paragraph = paragraph(
+                        &font_collection,
+                        value,
+                        &batch.font_family,
+                        batch.font_size,
+                        &options,
+                    );
+                    paragraph.layout(f32::INFINITY);
+                    (paragraph.max_intrinsic_width(), value)
+                })
+                .max_by(|(a, _), (b, _)| a.total_cmp(b))
+                .map_or("", |(_, value)| value)
+        })
+        .collect()
+}
+
+/// Problem with a font used by the text fields of a certificate
+#[derive(Debug, PartialEq, Eq)]
+pub enum GlyphWarning {
+    /// No installed font goes by this family name
+    MissingFont(String),
+    /// The font has no glyph for these characters, they are drawn with another installed font
+    FallbackGlyphs(String, BTreeSet<char>),
+    /// No installed font has a glyph for these characters, they show up as boxes
+    MissingGlyphs(String, BTreeSet<char>),
+}
+
+impl std::fmt::Display for GlyphWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GlyphWarning::MissingFont(family) => write!(f, "Font {family} is not installed"),
+            GlyphWarning::FallbackGlyphs(family, chars) => {
+                let chars: String = chars.iter().collect();
+                write!(f, "Font {family} lacks {chars}, drawn with a fallback font")
             }
-            std::io::ErrorKind::PermissionDenied => {
-                // send to frontend somehow
-                panic!("{e}")
+            GlyphWarning::MissingGlyphs(family, chars) => {
+                let chars: String = chars.iter().collect();
+                write!(
+                    f,
+                    "Neither {family} nor any other installed font can display: {chars}"
+                )
             }
-            _ => {
-                panic!("{e}")
+        }
+    }
+}
+
+/// Checks that the fonts of `record`'s text fields cover every character they are given
+pub fn missing_glyphs(batch: &Batch, record: &StringRecord) -> Vec<GlyphWarning> {
+    let mut texts: HashMap<&str, String> = HashMap::new();
+    for (kind, ..) in &batch.fields {
+        let (family, text) = match kind {
+            FieldKind::Column(index) => {
+                let Some(text) = batch.column_text(record, *index) else {
+                    continue;
+                };
+                (batch.font_family.as_str(), text.to_string())
+            }
+            FieldKind::Static {
+                text, font_family, ..
+            } => (font_family.as_str(), text.clone()),
+            FieldKind::Combined(template) => (
+                batch.font_family.as_str(),
+                fill_template(template, &batch.columns, record),
+            ),
+            FieldKind::Date {
+                format,
+                column,
+                locale,
+            } => {
+                let Some(text) = date_text(format, *column, *locale, record) else {
+                    continue;
+                };
+                (batch.font_family.as_str(), text)
             }
-        },
-        _ => {}
+            _ => continue,
+        };
+        texts.entry(family).or_default().push_str(&text);
+    }
+
+    let font_mgr = FontMgr::new();
+    let mut warnings = Vec::new();
+    for (family, text) in texts {
+        let Some(typeface) = font_mgr.match_family_style(family, FontStyle::normal()) else {
+            warnings.push(GlyphWarning::MissingFont(family.to_string()));
+            continue;
+        };
+        let (fallback, missing): (BTreeSet<char>, BTreeSet<char>) = text
+            .chars()
+            .filter(|c| !c.is_whitespace() && !c.is_control())
+            .filter(|&c| typeface.unichar_to_glyph(c as i32) == 0)
+            // the paragraph falls back to any installed font that has the character
+            .partition(|&c| {
+                font_mgr
+                    .match_family_style_character(family, FontStyle::normal(), &[], c as i32)
+                    .is_some()
+            });
+        if !fallback.is_empty() {
+            warnings.push(GlyphWarning::FallbackGlyphs(family.to_string(), fallback));
+        }
+        if !missing.is_empty() {
+            warnings.push(GlyphWarning::MissingGlyphs(family.to_string(), missing));
+        }
+    }
+
+    warnings
+}
+
+fn draw_qr_code(canvas: &mut Canvas, data: &str, rect: Rect) -> anyhow::Result<()> {
+    let code = QrCode::new(data).with_context(|| format!("Failed to encode QR code for {data}"))?;
+    let width = code.width();
+    let module = rect.width().min(rect.height()) / width as f32;
+
+    let paint = Paint::default();
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == qrcode::Color::Dark {
+            let (x, y) = ((i % width) as f32, (i / width) as f32);
+            canvas.draw_rect(
+                Rect::from_xywh(
+                    rect.left + x * module,
+                    rect.top + y * module,
+                    module,
+                    module,
+                ),
+                &paint,
+            );
+        }
     }
-    fs::write(format!("output/{filename}"), data.as_bytes()).expect("failed to write to file");
+
+    Ok(())
+}
+
+fn draw_barcode(
+    canvas: &mut Canvas,
+    symbology: Symbology,
+    data: &str,
+    rect: Rect,
+) -> anyhow::Result<()> {
+    let bars = symbology
+        .encode(data)
+        .with_context(|| format!("Failed to encode {symbology:?} barcode for {data}"))?;
+    let bar_width = rect.width() / bars.len() as f32;
+
+    let paint = Paint::default();
+    for (i, bar) in bars.into_iter().enumerate() {
+        if bar == 1 {
+            canvas.draw_rect(
+                Rect::from_xywh(
+                    rect.left + i as f32 * bar_width,
+                    rect.top,
+                    bar_width,
+                    rect.height(),
+                ),
+                &paint,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Encodes a rendered certificate in the batch's output format and quality
+pub fn encode_certificate(image: &Image, batch: &Batch) -> anyhow::Result<Data> {
+    image
+        .encode_to_data_with_quality(batch.format.encoded_format(), batch.quality.into())
+        .context("Failed to encode certificate")
+}
+
+/// Writes a rendered certificate to `filename` in the batch's output directory
+pub fn save_certificate(image: &Image, batch: &Batch, filename: &str) -> anyhow::Result<()> {
+    let data = encode_certificate(image, batch)?;
+    let path = batch.output_dir.join(filename);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create output directory")?;
+    }
+    fs::write(&path, data.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
 }
 
 pub fn add_fonts() -> FontDefinitions {
@@ -203,3 +1684,269 @@ pub fn fix_text(text: &str) -> String {
 
     text.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(values: &[&str]) -> StringRecord {
+        StringRecord::from(values.to_vec())
+    }
+
+    #[test]
+    fn dates_use_arabic_month_and_weekday_names() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        assert_eq!(
+            format_date(date, "%d %B %Y", Language::Arabic).unwrap(),
+            "05 مارس 2024"
+        );
+        assert_eq!(
+            format_date(date, "%A %e %b", Language::Arabic).unwrap(),
+            "الثلاثاء  5 مارس"
+        );
+        assert_eq!(
+            format_date(date, "%a %d %B %Y", Language::English).unwrap(),
+            "Tue 05 March 2024"
+        );
+        // an escaped percent sign isn't a month name
+        assert_eq!(
+            format_date(date, "%%B %m", Language::Arabic).unwrap(),
+            "%B 03"
+        );
+    }
+
+    #[test]
+    fn invalid_date_formats_are_errors() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        for format in ["%d %", "%Q", "%H:%M", "%d %B %Y %Z"] {
+            for locale in [Language::English, Language::Arabic] {
+                assert!(format_date(date, format, locale).is_err(), "{format}");
+            }
+        }
+    }
+
+    #[test]
+    fn date_fields_read_their_column() {
+        let row = record(&["Ahmed", "2024-03-05", "05/03/2024", "soon"]);
+        let text = |column| date_text("%d %B", Some(column), Language::Arabic, &row);
+        assert_eq!(text(1).as_deref(), Some("05 مارس"));
+        assert_eq!(text(2).as_deref(), Some("05 مارس"));
+        assert_eq!(text(3), None);
+        assert_eq!(text(4), None);
+        assert_eq!(date_text("%H", Some(1), Language::English, &row), None);
+    }
+
+    #[test]
+    fn placeholders_are_filled_from_the_record() {
+        let columns = record(&["first", "last", "الاسم"]);
+        let row = record(&["Ahmed", "Ali", "أحمد"]);
+        assert_eq!(fill_template("{first} {last}", &columns, &row), "Ahmed Ali");
+        assert_eq!(fill_template("{الاسم}", &columns, &row), "أحمد");
+        assert_eq!(
+            fill_template("{first}, {missing}", &columns, &row),
+            "Ahmed, {missing}"
+        );
+        assert_eq!(
+            fill_template("no placeholders", &columns, &row),
+            "no placeholders"
+        );
+    }
+
+    #[test]
+    fn columns_without_a_header_are_numbered() {
+        assert_eq!(
+            numbered_columns(3, "Column"),
+            record(&["Column 1", "Column 2", "Column 3"])
+        );
+        assert_eq!(numbered_columns(1, "عمود"), record(&["عمود 1"]));
+        assert!(numbered_columns(0, "Column").is_empty());
+    }
+
+    /// PNG with an `IHDR` chunk and `chunks` after it, checksums are left zeroed
+    fn png(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, data) in [(b"IHDR", vec![0; 13])].iter().chain(chunks) {
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(*kind);
+            png.extend(data);
+            png.extend([0; 4]);
+        }
+        png
+    }
+
+    fn phys(x: u32, y: u32, unit: u8) -> Vec<u8> {
+        [&x.to_be_bytes()[..], &y.to_be_bytes(), &[unit]].concat()
+    }
+
+    /// JPEG with `segments` and the JFIF header after them
+    fn jpeg(segments: &[(u8, Vec<u8>)], unit: u8, x: u16, y: u16) -> Vec<u8> {
+        let jfif = [
+            &b"JFIF\0\x01\x01"[..],
+            &[unit],
+            &x.to_be_bytes(),
+            &y.to_be_bytes(),
+            &[0, 0],
+        ]
+        .concat();
+        let mut jpeg = vec![0xff, 0xd8];
+        for (marker, data) in segments.iter().chain([&(0xe0, jfif)]) {
+            jpeg.extend([0xff, *marker]);
+            jpeg.extend((data.len() as u16 + 2).to_be_bytes());
+            jpeg.extend(data);
+        }
+        jpeg.extend([0xff, 0xda]);
+        jpeg
+    }
+
+    fn rounded(dpi: Option<(f32, f32)>) -> Option<(f32, f32)> {
+        dpi.map(|(x, y)| (x.round(), y.round()))
+    }
+
+    #[test]
+    fn png_dpi_comes_from_phys() {
+        let dpi = |chunks: &[(&[u8; 4], Vec<u8>)]| rounded(template_dpi(&png(chunks)));
+        assert_eq!(dpi(&[(b"pHYs", phys(11811, 11811, 1))]), Some((300., 300.)));
+        assert_eq!(
+            dpi(&[(b"tEXt", vec![b'a'; 20]), (b"pHYs", phys(2835, 5669, 1))]),
+            Some((72., 144.))
+        );
+        // an aspect ratio without a unit isn't a resolution
+        assert_eq!(dpi(&[(b"pHYs", phys(1, 1, 0))]), None);
+        // pHYs has to come before the image data
+        assert_eq!(
+            dpi(&[(b"IDAT", vec![0; 8]), (b"pHYs", phys(11811, 11811, 1))]),
+            None
+        );
+        assert_eq!(dpi(&[]), None);
+    }
+
+    #[test]
+    fn jpeg_dpi_comes_from_jfif() {
+        assert_eq!(template_dpi(&jpeg(&[], 1, 300, 300)), Some((300., 300.)));
+        assert_eq!(
+            rounded(template_dpi(&jpeg(&[], 2, 118, 59))),
+            Some((300., 150.))
+        );
+        assert_eq!(template_dpi(&jpeg(&[], 0, 1, 1)), None);
+        // segments before the JFIF header are skipped
+        assert_eq!(
+            template_dpi(&jpeg(&[(0xe1, vec![0; 30])], 1, 96, 96)),
+            Some((96., 96.))
+        );
+    }
+
+    #[test]
+    fn truncated_images_have_no_dpi() {
+        let png = png(&[(b"pHYs", phys(11811, 11811, 1))]);
+        let jpeg = jpeg(&[], 1, 300, 300);
+        // cut anywhere before the end of the resolution
+        for (image, end) in [(&png[..], 50), (&jpeg[..], 20)] {
+            assert!(template_dpi(&image[..end]).is_some());
+            for len in 0..end {
+                assert_eq!(template_dpi(&image[..len]), None);
+            }
+        }
+        assert_eq!(template_dpi(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn workbook_dates_are_written_as_dates() {
+        assert_eq!(cell_text(&DataType::DateTime(45356.)), "2024-03-05");
+        assert_eq!(cell_text(&DataType::DateTime(45356.75)), "2024-03-05 18:00");
+        assert_eq!(cell_text(&DataType::Float(45356.)), "45356");
+        assert_eq!(cell_text(&DataType::String(String::from("أحمد"))), "أحمد");
+        assert_eq!(cell_text(&DataType::Empty), "");
+    }
+
+    #[test]
+    fn invalid_workbooks_are_errors() {
+        let error = read_xlsx(b"name,email\n", true, "Column").unwrap_err();
+        assert!(format!("{error:#}").contains("Not a valid Excel workbook"));
+    }
+
+    /// `c` repeated up to `len` as the OS counts it
+    fn repeat_to(c: char, len: usize) -> String {
+        c.to_string().repeat(len / os_len(&c.to_string()))
+    }
+
+    #[test]
+    fn names_within_the_limit_are_kept() {
+        assert_eq!(
+            shorten_name(&repeat_to('a', 251), Some("png"), usize::MAX),
+            None
+        );
+        assert_eq!(
+            shorten_name(&repeat_to('م', 250), Some("png"), usize::MAX),
+            None
+        );
+        assert_eq!(shorten_name(&repeat_to('م', 96), Some("png"), 100), None);
+        assert_eq!(shorten_name(&repeat_to('a', 255), None, usize::MAX), None);
+    }
+
+    #[test]
+    fn long_ascii_names_are_cut_to_the_limit() {
+        let stem = repeat_to('a', 252);
+        let name = shorten_name(&stem, Some("png"), usize::MAX).unwrap();
+        assert_eq!(os_len(&name), MAX_NAME_LEN);
+        assert!(name.starts_with("aaaa"));
+        assert!(name.ends_with(".png"));
+
+        // names that only differ in the cut part stay apart
+        let other = shorten_name(&format!("{}b", &stem[1..]), Some("png"), usize::MAX).unwrap();
+        assert_ne!(name, other);
+        // and the same name is always cut the same way
+        assert_eq!(shorten_name(&stem, Some("png"), usize::MAX), Some(name));
+    }
+
+    #[test]
+    fn long_arabic_names_are_cut_between_characters() {
+        let stem = repeat_to('م', 300);
+        for max_len in [MAX_NAME_LEN, 100, 101] {
+            let name = shorten_name(&stem, Some("png"), max_len).unwrap();
+            assert!(os_len(&name) <= max_len);
+            let (kept, suffix) = name.split_once('~').unwrap();
+            assert!(!kept.is_empty());
+            assert!(kept.chars().all(|c| c == 'م'));
+            assert_eq!(suffix.len(), "00000000.png".len());
+        }
+    }
+
+    #[test]
+    fn short_paths_are_kept() {
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            fit_file_name(&dir, None, "أحمد-علي", "png"),
+            (String::from("أحمد-علي.png"), false)
+        );
+        assert_eq!(
+            fit_file_name(&dir, Some("group"), "a-b", "pdf"),
+            (String::from("group/a-b.pdf"), false)
+        );
+    }
+
+    #[test]
+    fn long_paths_are_cut_to_the_os_limit() {
+        let dir = std::env::temp_dir();
+        let dir = dir.join(repeat_to(
+            'd',
+            MAX_PATH_LEN - os_len(&dir.to_string_lossy()) - 121,
+        ));
+        let path_len = |filename: &str| os_len(&dir.join(filename).to_string_lossy());
+
+        let (filename, shortened) = fit_file_name(&dir, None, &repeat_to('م', 200), "png");
+        assert!(shortened);
+        assert!(path_len(&filename) <= MAX_PATH_LEN);
+
+        let (filename, shortened) = fit_file_name(
+            &dir,
+            Some(&repeat_to('ف', 200)),
+            &repeat_to('م', 200),
+            "png",
+        );
+        assert!(shortened);
+        assert!(path_len(&filename) <= MAX_PATH_LEN);
+        let (folder, name) = filename.split_once('/').unwrap();
+        assert!(folder.starts_with('ف'));
+        assert!(name.starts_with('م') && name.ends_with(".png"));
+    }
+}
@@ -0,0 +1,66 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+static LOGGER: Logger = Logger {
+    file: Mutex::new(None),
+};
+
+/// Writes to stderr and, if enabled, appends to a log file so remote users can send it over
+struct Logger {
+    file: Mutex<Option<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // dependencies only get through with warnings and errors, they are too chatty otherwise
+        metadata.level() <= log::max_level()
+            && (metadata.target().starts_with("certs") || metadata.level() <= Level::Warn)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!(
+            "{} {:<5} {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            record.level(),
+            record.args()
+        );
+        eprintln!("{line}");
+        if let Some(file) = self.file.lock().expect("log file lock").as_mut() {
+            let _ = writeln!(file, "{line}");
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().expect("log file lock").as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Installs the logger at the info level, call once before anything logs
+pub fn init() {
+    log::set_logger(&LOGGER).expect("logger already set");
+    log::set_max_level(LevelFilter::Info);
+}
+
+/// Changes the level and starts or stops appending to `file`
+pub fn configure(level: LevelFilter, file: Option<&Path>) {
+    log::set_max_level(level);
+
+    let file = file.and_then(|path| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| eprintln!("Failed to open log file {}: {e}", path.display()))
+            .ok()
+    });
+    *LOGGER.file.lock().expect("log file lock") = file;
+}
@@ -1,31 +1,394 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use certs::{fix_text, send_email, Config, EmailCreds, TextRect, Wrapper};
+use anyhow::Context;
+use certs::i18n::{Language, Strings};
+use certs::{
+    add_serials, attachment_name, decode_template, email_body, fit_file_name, fix_text,
+    format_date, normalize_address, num_cpus, numbered_columns, read_template, read_xlsx,
+    send_email, template_dpi, write_manifest, Config, EmailCreds, Field, FieldKind, FieldMapping,
+    FitMode, Layout, OutputFormat, PageSize, SentLog, SerialKind, Symbology, TextAnchor,
+    TextOptions, TextRect, ThemePreference, VerticalAlign, Wrapper, EMAIL_SUBJECT, SERIAL_COLUMN,
+};
 use csv::StringRecord;
+use lettre::Address;
+use log::{debug, info, warn, LevelFilter};
 use rand::Rng;
-use skia_safe::Point;
+use skia_safe::{EncodedImageFormat, ISize, Image};
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         mpsc::{Receiver, TryRecvError},
-        Arc,
+        Arc, Condvar, Mutex,
     },
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use certs::{add_fonts, generate_certificate};
+use certs::{
+    add_fonts, generate_certificate, missing_glyphs, render_certificate, widest_record, Batch,
+    EmailQueue, GlyphWarning, QueuedEmail,
+};
 use eframe::{
-    egui::{self, Button, RichText, Sense, Ui},
-    emath::Align2,
-    epaint::{Color32, Rect, Rounding, Stroke, Vec2},
+    egui::{self, Button, Key, Modifiers, RichText, Sense, Ui},
+    emath::{Align, Align2},
+    epaint::{Color32, ColorImage, FontId, Rect, Rounding, Stroke, Vec2},
     App,
 };
 use egui_extras::{Column, RetainedImage, TableBuilder};
 use native_dialog::FileDialog;
-use rayon::prelude::*;
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+/// How much the template is scaled down in the layout window
+const PREVIEW_SCALE: f32 = 2.5;
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Template scaled down for the layout window, so big templates don't take a full size texture.
+/// It's shown at 1/`PREVIEW_SCALE` of the template in points, the texture gets one pixel per
+/// physical pixel of that so it stays sharp on HiDPI screens
+fn thumbnail(template: &[u8], pixels_per_point: f32) -> anyhow::Result<ColorImage> {
+    let image = image::load_from_memory(template)?;
+    let scale = (pixels_per_point / PREVIEW_SCALE).min(1.);
+    let thumbnail = image
+        .thumbnail(
+            (image.width() as f32 * scale).ceil() as u32,
+            (image.height() as f32 * scale).ceil() as u32,
+        )
+        .to_rgba8();
+    let size = [thumbnail.width() as usize, thumbnail.height() as usize];
+
+    Ok(ColorImage::from_rgba_unmultiplied(size, thumbnail.as_raw()))
+}
+
+fn field_label(t: &Strings, columns: &StringRecord, kind: &FieldKind) -> String {
+    match kind {
+        FieldKind::Column(i) => fix_text(&columns[*i]),
+        FieldKind::QrCode(_) => fix_text(t.qr_code),
+        FieldKind::Barcode(symbology, _) => format!("{}: {symbology:?}", fix_text(t.barcode)),
+        FieldKind::Image(path) => path
+            .file_name()
+            .map_or(fix_text(t.image), |name| name.to_string_lossy().to_string()),
+        FieldKind::Static { text, .. } if text.is_empty() => fix_text(t.text),
+        FieldKind::Static { text, .. } => fix_text(text),
+        FieldKind::Combined(template) if template.is_empty() => fix_text(t.combined),
+        FieldKind::Combined(template) => fix_text(template),
+        FieldKind::Date { .. } => fix_text(t.date),
+    }
+}
+
+fn glyph_warning_text(t: &Strings, warning: &GlyphWarning) -> String {
+    match warning {
+        GlyphWarning::MissingFont(family) => {
+            format!("{}: {family}", fix_text(t.font_not_installed))
+        }
+        GlyphWarning::FallbackGlyphs(family, chars) => format!(
+            "{} ({family}): {}",
+            fix_text(t.fallback_glyphs),
+            chars.iter().collect::<String>()
+        ),
+        GlyphWarning::MissingGlyphs(family, chars) => format!(
+            "{} ({family}): {}",
+            fix_text(t.missing_glyphs),
+            chars.iter().collect::<String>()
+        ),
+    }
+}
+
+/// What to do with certificates left in the output directory by a previous run
+#[derive(Clone, Copy)]
+enum ExistingFiles {
+    Overwrite,
+    Skip,
+    /// Write this run into a new timestamped subfolder
+    NewFolder,
+}
+
+/// Whether an address that appears more than once gets every certificate or just the first
+#[derive(Clone, Copy)]
+enum Duplicates {
+    SendAll,
+    SendOnce,
+}
+
+/// A send waiting for the user to look over where it goes
+struct SendConfirmation {
+    duplicates: Option<Duplicates>,
+    recipients: usize,
+    email_column: String,
+    /// Where the certificates are generated before they're attached
+    output_dir: PathBuf,
+}
+
+/// What happened to the rows of the last imported CSV
+#[derive(Default)]
+struct ImportStats {
+    /// Rows dropped by the row filter
+    incomplete: usize,
+    /// Rows the CSV reader couldn't parse
+    malformed: usize,
+    /// Records with a parsable address, if there is an email column
+    valid_emails: Option<usize>,
+}
+
+/// Which rows of the CSV are dropped for having empty cells
+#[derive(Clone, PartialEq)]
+enum RowFilter {
+    KeepAll,
+    RequireAll,
+    /// Drop rows where any of these columns is empty
+    Required(BTreeSet<usize>),
+}
+
+fn apply_theme(ctx: &egui::Context, theme: ThemePreference, system: Option<eframe::Theme>) {
+    let theme = match theme {
+        ThemePreference::System => system.unwrap_or(eframe::Theme::Dark),
+        ThemePreference::Light => eframe::Theme::Light,
+        ThemePreference::Dark => eframe::Theme::Dark,
+    };
+    ctx.set_visuals(theme.egui_visuals());
+}
+
+/// Outcome of the last batch, kept until the next one starts
+struct RunSummary {
+    total: usize,
+    failures: Vec<String>,
+    /// Certificates whose text didn't fit, with the fields that overflowed
+    overflows: Vec<String>,
+    /// File names that were too long, as they were saved
+    shortened: Vec<String>,
+    elapsed: Duration,
+}
+
+/// Rows whose certificates failed or overflowed in the last generation run, and where that run
+/// wrote them so a retry replaces them in place
+#[derive(Default)]
+struct FailedRows {
+    output_dir: PathBuf,
+    rows: Vec<usize>,
+}
+
+/// Where a row is in the running email batch
+#[derive(Clone, Copy)]
+enum SendStatus {
+    Pending,
+    Sent,
+    Failed,
+}
+
+/// A read template and its thumbnail for the layout window
+struct PickedTemplate {
+    bytes: Vec<u8>,
+    thumbnail: ColorImage,
+}
+
+/// A picked template file, `None` if the dialog was cancelled
+type TemplatePick = Option<(PathBuf, anyhow::Result<PickedTemplate>)>;
+
+/// Asks for a template file, unless `path` is given, and reads it and makes its thumbnail on
+/// another thread since rasterizing a PDF can take a few seconds
+fn pick_template(
+    path: Option<PathBuf>,
+    pdf_dpi: u32,
+    pixels_per_point: f32,
+) -> Receiver<TemplatePick> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let path = match path {
+            Some(path) => Some(path),
+            None => {
+                let current_dir = std::env::current_dir()?;
+
+                FileDialog::new()
+                    .set_location(&current_dir)
+                    .add_filter("Template Image", &["jpg", "png", "jpeg", "webp", "pdf"])
+                    .show_open_single_file()?
+            }
+        };
+
+        sender.send(path.map(|path| {
+            let template = read_template(&path, pdf_dpi).and_then(|bytes| {
+                Ok(PickedTemplate {
+                    thumbnail: thumbnail(&bytes, pixels_per_point)?,
+                    bytes,
+                })
+            });
+            (path, template)
+        }))?;
+        anyhow::Ok(())
+    });
+
+    receiver
+}
+
+/// Logs the fields of `filename` that overflowed their rectangle
+fn note_overflows(log: &Mutex<Vec<String>>, filename: &str, fields: Vec<String>) {
+    if fields.is_empty() {
+        return;
+    }
+    let overflow = format!("{filename}: {}", fields.join(", "));
+    warn!("Text doesn't fit in {overflow}");
+    log.lock().expect("overflow log lock").push(overflow);
+}
+
+fn show_summary(ui: &mut Ui, t: &Strings, summary: &RunSummary) {
+    ui.label(format!(
+        "{}: {} / {}",
+        fix_text(t.succeeded),
+        summary.total - summary.failures.len(),
+        summary.total
+    ));
+    ui.label(format!("{}: {:.1?}", fix_text(t.elapsed), summary.elapsed));
+    if !summary.failures.is_empty() {
+        ui.label(format!(
+            "{}: {}",
+            fix_text(t.failures),
+            summary.failures.len()
+        ));
+        egui::ScrollArea::vertical()
+            .max_height(200.)
+            .show(ui, |ui| {
+                for failure in &summary.failures {
+                    ui.label(failure);
+                }
+            });
+    }
+    if !summary.overflows.is_empty() {
+        ui.label(format!(
+            "{}: {}",
+            fix_text(t.overflows),
+            summary.overflows.len()
+        ));
+        egui::ScrollArea::vertical()
+            .id_source("overflows")
+            .max_height(200.)
+            .show(ui, |ui| {
+                for overflow in &summary.overflows {
+                    ui.label(fix_text(overflow));
+                }
+            });
+    }
+    if !summary.shortened.is_empty() {
+        ui.label(format!(
+            "{}: {}",
+            fix_text(t.shortened_names),
+            summary.shortened.len()
+        ));
+        egui::ScrollArea::vertical()
+            .id_source("shortened")
+            .max_height(200.)
+            .show(ui, |ui| {
+                for name in &summary.shortened {
+                    ui.label(fix_text(name));
+                }
+            });
+    }
+}
+
+/// Turns a column value into a folder name that's valid on every OS
+fn folder_name(value: &str) -> String {
+    let name = value
+        .trim()
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+    if name.is_empty() || name == "." || name == ".." {
+        return String::from("_");
+    }
+
+    name
+}
+
+/// Folder inside `output_dir` named after the current time, for a run that shouldn't touch
+/// the certificates of earlier ones
+fn run_folder(output_dir: &Path) -> PathBuf {
+    output_dir.join(chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string())
+}
+
+/// Layout written every `AUTOSAVE_INTERVAL`, removed again when the app closes normally
+fn autosave_path() -> PathBuf {
+    dirs::config_dir()
+        .expect("config directory")
+        .join("certs/autosave.json")
+}
+
+fn queue_path() -> PathBuf {
+    dirs::config_dir()
+        .expect("config directory")
+        .join("certs/queue.json")
+}
+
+/// Writes the email queue to disk, a failure to do so is reported with the run's errors
+fn save_queue(queue: &Mutex<EmailQueue>, failures: &mut Vec<String>) {
+    if let Err(e) = queue.lock().expect("email queue lock").save(&queue_path()) {
+        failures.push(format!("{e:#}"));
+    }
+}
+
+/// Horizontal layout that runs right to left for RTL languages
+fn row_layout(language: Language) -> egui::Layout {
+    if language.is_rtl() {
+        egui::Layout::right_to_left(Align::Center)
+    } else {
+        egui::Layout::left_to_right(Align::Center)
+    }
+}
+
+fn configure_logging(config: &Config) {
+    let log_file = dirs::config_dir()
+        .expect("config directory")
+        .join("certs/certs.log");
+    certs::logger::configure(config.log_level, config.log_to_file.then_some(&*log_file));
+}
+
+fn save_config(config: &Config) {
+    let config_dir = dirs::config_dir().expect("config directory").join("certs/");
+    let current_config = toml::to_string(config).expect("Config to string");
+    fs::write(config_dir.join("config.toml"), current_config).expect("save config");
+}
+
+/// Runs `work` on a pool of `threads` workers, so a big batch doesn't starve the UI thread
+fn in_pool(threads: usize, work: impl FnOnce() -> Vec<String> + Send) -> Vec<String> {
+    match ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(work),
+        Err(e) => vec![format!("Failed to start worker threads: {e}")],
+    }
+}
+
+/// Lets at most `limit` threads do their work at once, the rest wait for a free slot
+struct Throttle {
+    running: Mutex<usize>,
+    freed: Condvar,
+    limit: usize,
+}
+
+impl Throttle {
+    fn new(limit: usize) -> Self {
+        Self {
+            running: Mutex::new(0),
+            freed: Condvar::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    fn run<T>(&self, work: impl FnOnce() -> T) -> T {
+        let mut running = self
+            .freed
+            .wait_while(self.running.lock().expect("throttle lock"), |running| {
+                *running >= self.limit
+            })
+            .expect("throttle lock");
+        *running += 1;
+        drop(running);
+
+        let result = work();
+        *self.running.lock().expect("throttle lock") -= 1;
+        self.freed.notify_one();
+        result
+    }
+}
 
 fn main() {
+    certs::logger::init();
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "Certificates app",
@@ -37,21 +400,106 @@ fn main() {
 struct CertApp {
     columns: StringRecord,
     records: Vec<StringRecord>,
+    /// Every readable row of the CSV, before the row filter
+    raw_records: Vec<StringRecord>,
+    row_filter: RowFilter,
+    /// Name the columns `Column 1`, `Column 2`, … in the UI language and keep the first row as a
+    /// record
+    first_row_is_data: bool,
+    selected_record: Option<usize>,
+    import_stats: Option<ImportStats>,
+    /// Why the last CSV or workbook failed to import
+    import_error: Option<String>,
     template_window_open: bool,
-    email_window_open: bool,
+    templates_window_open: bool,
+    preferences_window_open: bool,
     send_email_window_open: bool,
     certificates_window_open: bool,
+    preview_window_open: bool,
+    /// Fonts of the last preview that can't display all of its text
+    glyph_warnings: Vec<GlyphWarning>,
+    preview: Option<RetainedImage>,
+    /// Row shown in the preview, `None` for the widest values preview
+    previewed_row: Option<usize>,
+    existing_files_window_open: bool,
+    existing_files: usize,
+    duplicates_window_open: bool,
+    send_confirmation: Option<SendConfirmation>,
+    /// Addresses that appear more than once, with how often
+    duplicates: Vec<(String, usize)>,
     csv_file_picker_receiver: Option<Receiver<Option<PathBuf>>>,
-    image_file_picker_receiver: Option<Receiver<Option<PathBuf>>>,
+    image_file_picker_receiver: Option<Receiver<TemplatePick>>,
+    /// Record to generate alone, waiting for where to save it
+    single_picker_receiver: Option<(StringRecord, Receiver<Option<PathBuf>>)>,
     status: String,
     image: Option<RetainedImage>,
+    /// Display scale the template thumbnail was made for
+    pixels_per_point: f32,
     current_rect: usize,
-    rects: Vec<(TextRect, Color32)>,
-    template: Arc<Vec<u8>>,
+    fields: Vec<Field>,
+    serials: Option<SerialKind>,
+    template: Option<Image>,
+    template_error: Option<String>,
+    template_dimensions: ISize,
+    template_dpi: Option<(f32, f32)>,
+    /// File name of the template, which names the sent log of the event
+    template_name: String,
+    template_column: Option<usize>,
+    /// Column whose value names the subfolder each certificate goes in
+    group_column: Option<usize>,
+    /// Columns whose values are joined into each certificate's file name
+    name_columns: [usize; 2],
+    templates: HashMap<String, Image>,
+    /// Value of `template_column` the open file dialog picks a template for
+    templates_picker_receiver: Option<(String, Receiver<TemplatePick>)>,
+    templates_error: Option<String>,
+    images: HashMap<PathBuf, Image>,
+    overlay_picker_receiver: Option<Receiver<Option<PathBuf>>>,
+    overlay_error: Option<String>,
+    mapping_export_receiver: Option<Receiver<Option<PathBuf>>>,
+    mapping_import_receiver: Option<Receiver<Option<PathBuf>>>,
+    /// Outcome of the last field mapping import or export
+    mapping_status: Option<String>,
     config: Config,
-    current_email_creds: EmailCreds,
-    t_handle: Option<JoinHandle<()>>,
+    preferences: Config,
+    system_theme: Option<eframe::Theme>,
+    output_dir_picker_receiver: Option<Receiver<Option<PathBuf>>>,
+    smtp_test_receiver: Option<Receiver<anyhow::Result<()>>>,
+    /// Outcome of the last SMTP connection test
+    smtp_test: Option<String>,
+    t_handle: Option<JoinHandle<Vec<String>>>,
+    /// When the running batch started and how many certificates it has
+    run_started: Option<(Instant, usize)>,
+    summary: Option<RunSummary>,
+    /// Filled by the workers of the running batch, see `note_overflows`
+    overflows: Arc<Mutex<Vec<String>>>,
+    /// Certificates of the running batch whose file names were too long
+    shortened: Vec<String>,
+    /// Rows whose certificates failed or overflowed in the last run, for retrying just those
+    failed_rows: Arc<Mutex<FailedRows>>,
+    /// Per row status of the last email batch, empty if the rows changed since
+    send_statuses: Arc<Mutex<Vec<Option<SendStatus>>>>,
+    skip_sent: bool,
+    /// Where the last batch was written, for opening it afterwards
+    last_output_dir: Option<PathBuf>,
     font_size: f32,
+    fixed_page_size: bool,
+    page_size: PageSize,
+    csv_path: Option<PathBuf>,
+    template_path: Option<PathBuf>,
+    /// Encoded template as read from `template_path`, PDFs already rasterized
+    template_bytes: Option<Arc<Vec<u8>>>,
+    /// Thumbnail being remade for a new display scale
+    thumbnail_receiver: Option<Receiver<anyhow::Result<ColorImage>>>,
+    /// Emails that failed to send, waiting for "Retry queued"
+    email_queue: Arc<Mutex<EmailQueue>>,
+    /// Autosave left behind by a session that didn't exit cleanly, until the user decides on it
+    restore: Option<Layout>,
+    /// Restored layout waiting for its CSV and template to finish importing
+    pending_layout: Option<Layout>,
+    last_autosave: Instant,
+    /// What was last written to the autosave, to skip writing it again unchanged
+    autosaved: String,
 }
 
 impl Default for CertApp {
@@ -64,7 +512,7 @@ impl Default for CertApp {
         let config_str = match fs::read_to_string(config_dir.join("config.toml")) {
             Ok(file) => file,
             Err(e) => {
-                println!("{e}");
+                info!("Creating a new config: {e}");
                 let new_config = toml::to_string(&Config::default()).expect("Config to string");
                 fs::write(config_dir.join("config.toml"), &new_config)
                     .expect("create new config file");
@@ -76,65 +524,201 @@ impl Default for CertApp {
         #[cfg(feature = "baba")]
         let config_str = include_str!("../baba.toml");
 
-        let config = toml::from_str::<Config>(&config_str).expect("deserialize config");
+        #[allow(unused_mut)]
+        let mut config = toml::from_str::<Config>(&config_str).expect("deserialize config");
+        configure_logging(&config);
+
+        #[cfg(not(feature = "baba"))]
+        if config.email.password.is_empty() {
+            if let Err(e) = config.email.load_password() {
+                warn!("{e:#}");
+            }
+        } else {
+            // password from an older plaintext config, move it to the keyring
+            match config.email.store_password() {
+                Ok(()) => save_config(&config),
+                Err(e) => warn!("{e:#}"),
+            }
+        }
 
         Self {
             columns: StringRecord::default(),
             records: Vec::default(),
+            raw_records: Vec::default(),
+            row_filter: RowFilter::RequireAll,
+            first_row_is_data: false,
+            selected_record: None,
+            import_stats: None,
+            import_error: None,
             template_window_open: false,
-            email_window_open: false,
+            templates_window_open: false,
+            preferences_window_open: false,
             send_email_window_open: false,
             certificates_window_open: false,
+            preview_window_open: false,
+            glyph_warnings: Vec::new(),
+            preview: None,
+            previewed_row: None,
+            existing_files_window_open: false,
+            existing_files: 0,
+            duplicates_window_open: false,
+            send_confirmation: None,
+            duplicates: Vec::default(),
             csv_file_picker_receiver: None,
             image_file_picker_receiver: None,
+            single_picker_receiver: None,
             status: String::new(),
             image: None,
+            pixels_per_point: 1.,
             current_rect: 0,
-            rects: Vec::default(),
-            template: Arc::default(),
-            config: config.clone(),
-            current_email_creds: config.email,
+            fields: Vec::default(),
+            serials: None,
+            template: None,
+            template_error: None,
+            template_dimensions: ISize::default(),
+            template_dpi: None,
+            template_name: String::new(),
+            template_column: None,
+            group_column: None,
+            name_columns: [0, 1],
+            templates: HashMap::default(),
+            templates_picker_receiver: None,
+            templates_error: None,
+            images: HashMap::default(),
+            overlay_picker_receiver: None,
+            overlay_error: None,
+            mapping_export_receiver: None,
+            mapping_import_receiver: None,
+            mapping_status: None,
+            font_size: config.font_size,
+            preferences: config.clone(),
+            system_theme: None,
+            config,
+            output_dir_picker_receiver: None,
+            smtp_test_receiver: None,
+            smtp_test: None,
             t_handle: None,
-            font_size: 40.,
+            run_started: None,
+            summary: None,
+            overflows: Arc::default(),
+            shortened: Vec::new(),
+            failed_rows: Arc::default(),
+            send_statuses: Arc::default(),
+            skip_sent: false,
+            last_output_dir: None,
+            fixed_page_size: false,
+            page_size: PageSize::default(),
+            csv_path: None,
+            template_path: None,
+            template_bytes: None,
+            thumbnail_receiver: None,
+            email_queue: Arc::new(Mutex::new(EmailQueue::load(&queue_path()).unwrap_or_else(
+                |e| {
+                    warn!("{e:#}");
+                    EmailQueue::default()
+                },
+            ))),
+            restore: Layout::load(&autosave_path())
+                .ok()
+                .filter(|layout| !layout.fields.is_empty()),
+            pending_layout: None,
+            last_autosave: Instant::now(),
+            autosaved: String::new(),
         }
     }
 }
 
 impl CertApp {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self::default()
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        cc.egui_ctx.set_fonts(add_fonts());
+        let app = Self {
+            system_theme: cc.integration_info.system_theme,
+            ..Self::default()
+        };
+        apply_theme(&cc.egui_ctx, app.config.theme, app.system_theme);
+        app
     }
-    fn set_template(&mut self, template: Arc<Vec<u8>>) {
+    fn set_template(&mut self, template: Option<Image>) {
         self.template = template;
     }
     fn table(&mut self, ui: &mut Ui) {
+        let rtl = self.config.language.is_rtl();
+        // RTL tables show the first column on the right
+        fn ordered(record: &StringRecord, rtl: bool) -> Vec<&str> {
+            let mut cells = record.iter().collect::<Vec<_>>();
+            if rtl {
+                cells.reverse();
+            }
+            cells
+        }
+        let t = self.config.language.strings();
+        let statuses = self.send_statuses.lock().expect("send status lock").clone();
+        let show_status = !statuses.is_empty();
         let table = TableBuilder::new(ui)
             .striped(true)
-            .cell_layout(egui::Layout::left_to_right(eframe::emath::Align::Center))
-            .columns(Column::remainder().resizable(true), self.columns.len());
+            .cell_layout(row_layout(self.config.language))
+            .columns(
+                Column::remainder().resizable(true),
+                self.columns.len() + usize::from(show_status),
+            );
 
         table
             .header(20., |mut header| {
-                for column in self.columns.iter() {
+                let status = |header: &mut egui_extras::TableRow| {
+                    header.col(|ui| {
+                        ui.strong(fix_text(t.status));
+                    });
+                };
+                if show_status && rtl {
+                    status(&mut header);
+                }
+                for column in ordered(&self.columns, rtl) {
                     header.col(|ui| {
                         ui.strong(fix_text(&column.to_uppercase()));
                     });
                 }
+                if show_status && !rtl {
+                    status(&mut header);
+                }
             })
             .body(|mut body| {
-                for record in self.records.iter() {
+                for (i, record) in self.records.iter().enumerate() {
                     body.row(18., |mut row| {
-                        for column in record {
+                        let status = |row: &mut egui_extras::TableRow| {
+                            row.col(|ui| match statuses.get(i).copied().flatten() {
+                                Some(SendStatus::Pending) => {
+                                    ui.colored_label(Color32::GRAY, fix_text(t.pending));
+                                }
+                                Some(SendStatus::Sent) => {
+                                    ui.colored_label(Color32::GREEN, fix_text(t.sent));
+                                }
+                                Some(SendStatus::Failed) => {
+                                    ui.colored_label(Color32::RED, fix_text(t.failed));
+                                }
+                                None => {}
+                            });
+                        };
+                        if show_status && rtl {
+                            status(&mut row);
+                        }
+                        for column in ordered(record, rtl) {
                             row.col(|ui| {
-                                ui.label(fix_text(column));
+                                let selected = self.selected_record == Some(i);
+                                if ui.selectable_label(selected, fix_text(column)).clicked() {
+                                    self.selected_record = Some(i);
+                                }
                             });
                         }
+                        if show_status && !rtl {
+                            status(&mut row);
+                        }
                     });
                 }
             });
     }
 
     fn import_csv(&mut self) -> anyhow::Result<()> {
+        let t = self.config.language.strings();
         if let Some(receiver) = self.csv_file_picker_receiver.as_ref() {
             if let Ok(path) = receiver.try_recv() {
                 let Some(path) = path else {
@@ -142,84 +726,481 @@ impl CertApp {
                     return Ok(());
                 };
 
-                let file = fs::read(path)?;
-                println!("set file");
-                let mut reader = csv::Reader::from_reader(&file[..]);
+                let file = fs::read(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                debug!("read {}", path.display());
 
-                self.columns = reader.headers()?.clone();
+                let mut malformed = 0;
+                let is_xlsx = path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("xlsx"));
+                if is_xlsx {
+                    (self.columns, self.raw_records) =
+                        read_xlsx(&file, !self.first_row_is_data, t.column)?;
+                } else {
+                    let mut reader = csv::ReaderBuilder::new()
+                        .has_headers(!self.first_row_is_data)
+                        .from_reader(&file[..]);
 
-                self.records = reader
-                    .records()
-                    .flatten()
-                    .filter(|r| r.iter().find(|r| r.is_empty()).is_none())
-                    .collect();
+                    self.columns = reader.headers().context("Invalid CSV header")?.clone();
+                    if self.first_row_is_data {
+                        self.columns = numbered_columns(self.columns.len(), t.column);
+                    }
 
-                let mut rng = rand::thread_rng();
-                for _ in 0..self.columns.len() {
-                    self.rects
-                        .push((TextRect::default(), rng.gen::<Wrapper<Color32>>().0))
+                    self.raw_records = reader
+                        .records()
+                        .filter_map(|r| match r {
+                            Ok(r) => Some(r),
+                            Err(e) => {
+                                warn!("{e}");
+                                malformed += 1;
+                                None
+                            }
+                        })
+                        .collect();
                 }
+                let serials = self.serials.take();
+                if let RowFilter::Required(required) = &mut self.row_filter {
+                    required.clear();
+                }
+                self.filter_records();
+                if let Some(stats) = &mut self.import_stats {
+                    stats.malformed = malformed;
+                }
+
+                let mut rng = rand::thread_rng();
+                let extra_fields = self
+                    .fields
+                    .drain(..)
+                    .filter(|field| !matches!(field.kind, FieldKind::Column(_)));
+                self.fields = (0..self.columns.len())
+                    .map(|i| Field {
+                        rect: TextRect::default(),
+                        color: rng.gen::<Wrapper<Color32>>().0,
+                        kind: FieldKind::Column(i),
+                        enabled: false,
+                        text: self
+                            .config
+                            .field_styles
+                            .get(&self.columns[i])
+                            .copied()
+                            .unwrap_or_default(),
+                        fallback: String::new(),
+                    })
+                    .chain(extra_fields)
+                    .collect();
+                self.current_rect = 0;
+                self.selected_record = None;
+                self.template_column = None;
+                self.group_column = None;
+                self.name_columns = [0, 1];
+                self.set_serials(serials);
+                self.csv_path = Some(path);
                 self.csv_file_picker_receiver = None;
-                println!("save records");
+                self.import_error = None;
+                info!("imported {} records", self.raw_records.len());
             }
         }
 
         Ok(())
     }
 
-    fn generate_certificates(&mut self) -> anyhow::Result<()> {
-        {
-            let records = self.records.clone();
-            let points = self
-                .rects
+    /// Rebuilds `records` from `raw_records` with the current row filter
+    fn filter_records(&mut self) {
+        // a column with fallback text never leaves its field empty
+        let fallbacks = self.fallbacks();
+        let filled =
+            |record: &StringRecord, i: usize| !record[i].is_empty() || fallbacks.contains_key(&i);
+        let keep = |record: &StringRecord| match &self.row_filter {
+            RowFilter::KeepAll => true,
+            RowFilter::RequireAll => (0..record.len()).all(|i| filled(record, i)),
+            RowFilter::Required(required) => required.iter().all(|&i| filled(record, i)),
+        };
+        let records = self
+            .raw_records
+            .iter()
+            .filter(|record| keep(record))
+            .cloned()
+            .collect::<Vec<_>>();
+        let incomplete = self.raw_records.len() - records.len();
+        self.records = records;
+        self.send_statuses.lock().expect("send status lock").clear();
+        self.failed_rows
+            .lock()
+            .expect("failed rows lock")
+            .rows
+            .clear();
+        if let Some(kind) = self.serials {
+            self.columns.truncate(self.columns.len() - 1);
+            add_serials(kind, &mut self.columns, &mut self.records);
+        }
+        self.selected_record = None;
+
+        let valid_emails = self.email_column().map(|i| {
+            self.records
                 .iter()
-                .map(|r| {
-                    let points = r.0.min();
-                    (
-                        Point::new(points.p1.x, points.p1.y) * 2.5,
-                        (points.p1.x - points.p2.x).abs() * 2.5,
-                    )
-                })
-                .collect::<Vec<(Point, f32)>>();
-            let template = self.template.clone();
-            let font_size = self.font_size;
+                .filter(|record| record[i].parse::<Address>().is_ok())
+                .count()
+        });
+        let malformed = self
+            .import_stats
+            .as_ref()
+            .map_or(0, |stats| stats.malformed);
+        self.import_stats = Some(ImportStats {
+            incomplete,
+            malformed,
+            valid_emails,
+        });
+    }
+
+    fn email_column(&self) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|s| s.to_lowercase() == "email" || s == "البريد الالكتروني")
+    }
+
+    fn set_serials(&mut self, kind: Option<SerialKind>) {
+        let had_serials = self.serials.is_some();
+        let len = self.columns.len() - usize::from(had_serials);
+        if had_serials {
+            self.columns.truncate(len);
+            for record in &mut self.records {
+                record.truncate(len);
+            }
+        }
+
+        match kind {
+            Some(kind) => {
+                add_serials(kind, &mut self.columns, &mut self.records);
+                if !had_serials {
+                    self.fields.push(Field {
+                        rect: TextRect::default(),
+                        color: rand::thread_rng().gen::<Wrapper<Color32>>().0,
+                        kind: FieldKind::Column(len),
+                        enabled: false,
+                        text: TextOptions::default(),
+                        fallback: String::new(),
+                    });
+                }
+            }
+            None if had_serials => {
+                self.fields
+                    .retain(|field| field.kind != FieldKind::Column(len));
+                self.current_rect = 0;
+                if self.template_column == Some(len) {
+                    self.template_column = None;
+                }
+                if self.group_column == Some(len) {
+                    self.group_column = None;
+                }
+                for (column, default) in self.name_columns.iter_mut().zip([0, 1]) {
+                    if *column == len {
+                        *column = default;
+                    }
+                }
+            }
+            None => {}
+        }
+
+        self.serials = kind;
+    }
+
+    /// Fallback text of the column fields that have one, keyed by column
+    fn fallbacks(&self) -> HashMap<usize, String> {
+        self.fields
+            .iter()
+            .filter(|field| !field.fallback.is_empty())
+            .filter_map(|field| match field.kind {
+                FieldKind::Column(i) => Some((i, field.fallback.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Field rectangles in template pixels, from their position on the scaled down preview
+    fn compute_rects(&self) -> Vec<(FieldKind, skia_safe::Rect, TextOptions)> {
+        self.fields
+            .iter()
+            .filter(|field| field.enabled)
+            .map(|field| {
+                let rect = field.rect.min();
+                (
+                    field.kind.clone(),
+                    skia_safe::Rect::new(
+                        rect.p1.x * PREVIEW_SCALE,
+                        rect.p1.y * PREVIEW_SCALE,
+                        rect.p2.x * PREVIEW_SCALE,
+                        rect.p2.y * PREVIEW_SCALE,
+                    ),
+                    field.text,
+                )
+            })
+            .collect()
+    }
+
+    fn batch(&self, template: Image) -> Batch {
+        Batch {
+            columns: self.columns.clone(),
+            fields: self.compute_rects(),
+            fallbacks: self.fallbacks(),
+            template,
+            template_column: self.template_column,
+            templates: self.templates.clone(),
+            images: self.images.clone(),
+            font_family: self.config.font_family.clone(),
+            font_size: self.font_size,
+            page: self.fixed_page_size.then_some(self.page_size),
+            output_dir: self.config.output_dir.clone(),
+            format: self.config.format,
+            quality: self.config.quality,
+            background: skia_safe::Color::from_rgb(
+                self.config.background[0],
+                self.config.background[1],
+                self.config.background[2],
+            ),
+            scale: self.config.output_scale,
+            supersample: self.config.supersample,
+        }
+    }
+
+    /// The template to generate with, or why generating can't start
+    fn ready_template(&self) -> Result<Image, String> {
+        let t = self.config.language.strings();
+        if self.records.is_empty() {
+            return Err(fix_text(t.needs_complete_row));
+        }
+        if self.columns.len() < 2 {
+            return Err(fix_text(t.needs_two_columns));
+        }
+        if let Some(error) = &self.template_error {
+            return Err(format!("{}: {error}", fix_text(t.invalid_template)));
+        }
+
+        self.template
+            .clone()
+            .ok_or_else(|| fix_text(t.choose_template))
+    }
+
+    /// Path of the record's certificate inside the output directory
+    fn filename(&self, record: &StringRecord) -> String {
+        self.checked_filename(record, &self.config.output_dir).0
+    }
 
+    /// `record`'s certificate file name inside `output_dir`, and whether part of it was too long
+    /// and got shortened
+    fn checked_filename(&self, record: &StringRecord, output_dir: &Path) -> (String, bool) {
+        let [first, second] = self.name_columns;
+        let stem = format!("{}-{}", &record[first], &record[second]);
+        let folder = self.group_column.map(|i| folder_name(&record[i]));
+        let (filename, shortened) = fit_file_name(
+            output_dir,
+            folder.as_deref(),
+            &stem,
+            self.config.format.extension(),
+        );
+        if shortened {
+            warn!("File name of {stem} is too long, saving it as {filename}");
+        }
+
+        (filename, shortened)
+    }
+
+    /// Records of `rows` with their file names inside `output_dir`, and the names that had to be
+    /// shortened to fit
+    fn jobs(
+        &self,
+        rows: &[usize],
+        output_dir: &Path,
+    ) -> (Vec<(usize, StringRecord, String)>, Vec<String>) {
+        let mut jobs = Vec::with_capacity(rows.len());
+        let mut shortened = Vec::new();
+        for &i in rows {
+            let Some(record) = self.records.get(i) else {
+                continue;
+            };
+            let (filename, was_shortened) = self.checked_filename(record, output_dir);
+            if was_shortened {
+                shortened.push(filename.clone());
+            }
+            jobs.push((i, record.clone(), filename));
+        }
+
+        (jobs, shortened)
+    }
+
+    /// Generates every row's certificate, or only `rows` into the last run's folder when retrying
+    fn generate_certificates(
+        &mut self,
+        existing: Option<ExistingFiles>,
+        rows: Option<Vec<usize>>,
+    ) -> anyhow::Result<()> {
+        {
             self.certificates_window_open = true;
-            self.status = String::from("Creating...");
+            let template = match self.ready_template() {
+                Ok(template) => template,
+                Err(e) => {
+                    self.status = e;
+                    return Ok(());
+                }
+            };
+
+            let mut batch = self.batch(template);
+            let retrying = rows.is_some();
+            if retrying {
+                batch.output_dir = self
+                    .failed_rows
+                    .lock()
+                    .expect("failed rows lock")
+                    .output_dir
+                    .clone();
+            }
+            let rows = rows.unwrap_or_else(|| (0..self.records.len()).collect());
+            let mut jobs;
+            (jobs, self.shortened) = self.jobs(&rows, &batch.output_dir);
+            let existing_count = jobs
+                .iter()
+                .filter(|(_, _, filename)| batch.output_dir.join(filename).exists())
+                .count();
+            match existing {
+                // retried rows replace what their failed attempt left behind
+                _ if retrying => {}
+                _ if existing_count == 0 => {}
+                None => {
+                    self.existing_files = existing_count;
+                    self.certificates_window_open = false;
+                    self.existing_files_window_open = true;
+                    return Ok(());
+                }
+                Some(ExistingFiles::Overwrite) => {}
+                Some(ExistingFiles::Skip) => {
+                    jobs.retain(|(_, _, filename)| !batch.output_dir.join(filename).exists());
+                }
+                Some(ExistingFiles::NewFolder) => {
+                    batch.output_dir = run_folder(&batch.output_dir);
+                    // the run folder makes every path longer
+                    (jobs, self.shortened) = self.jobs(&rows, &batch.output_dir);
+                }
+            }
+            self.status = fix_text(self.config.language.strings().creating);
+            self.last_output_dir = Some(batch.output_dir.clone());
 
+            let threads = self.config.threads;
+            // a retry only has some of the certificates, its manifest would replace the full one
+            let manifest = self.config.manifest && !retrying;
+            let in_order = self.config.in_order;
+            let overflows = Arc::clone(&self.overflows);
+            overflows.lock().expect("overflow log lock").clear();
+            let failed_rows = Arc::clone(&self.failed_rows);
+            *failed_rows.lock().expect("failed rows lock") = FailedRows {
+                output_dir: batch.output_dir.clone(),
+                rows: Vec::new(),
+            };
+            self.run_started = Some((Instant::now(), jobs.len()));
+            self.summary = None;
             self.t_handle = Some(std::thread::spawn(move || {
-                records.par_iter().for_each(move |record| {
-                    let filename = format!("{}-{}.png", &record[0], &record[1]);
-                    generate_certificate(
-                        record,
-                        points.clone(),
-                        template.clone(),
-                        &filename,
-                        font_size,
-                    );
-                });
+                in_pool(threads, || {
+                    let generate = |(i, record, filename): &(usize, StringRecord, String)| {
+                        let result = generate_certificate(&batch, record, filename);
+                        if result.as_ref().map_or(true, |fields| !fields.is_empty()) {
+                            failed_rows.lock().expect("failed rows lock").rows.push(*i);
+                        }
+                        result
+                            .map(|fields| note_overflows(&overflows, filename, fields))
+                            .err()
+                            .map(|e| format!("{filename}: {e:#}"))
+                    };
+                    let errors = if in_order {
+                        jobs.iter().map(generate).collect::<Vec<_>>()
+                    } else {
+                        jobs.par_iter().map(generate).collect()
+                    };
+                    let mut failures = Vec::new();
+                    let mut generated = Vec::new();
+                    for ((_, record, filename), error) in jobs.iter().zip(errors) {
+                        match error {
+                            Some(error) => failures.push(error),
+                            None => generated.push((record, filename.as_str())),
+                        }
+                    }
+                    if manifest {
+                        if let Err(e) =
+                            write_manifest(&batch.output_dir, &batch.columns, &generated)
+                        {
+                            failures.push(format!("{e:#}"));
+                        }
+                    }
+
+                    failures
+                })
             }));
         }
         Ok(())
     }
 
-    fn import_template(&mut self) -> anyhow::Result<()> {
-        if let Some(receiver) = self.image_file_picker_receiver.take() {
+    /// Asks where to save the selected row's certificate, outside the output directory
+    fn pick_single(&mut self) -> anyhow::Result<()> {
+        let Some(record) = self.selected_record.map(|i| self.records[i].clone()) else {
+            anyhow::bail!(fix_text(
+                self.config.language.strings().select_row_to_generate
+            ));
+        };
+        let filename = self.filename(&record);
+        let filename = Path::new(&filename)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let extension = self.config.format.extension();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.single_picker_receiver = Some((record, receiver));
+        std::thread::spawn(move || {
+            let current_dir = std::env::current_dir()?;
+
+            let path = FileDialog::new()
+                .set_location(&current_dir)
+                .set_filename(&filename)
+                .add_filter("Certificate", &[extension])
+                .show_save_single_file()?;
+
+            sender.send(path)?;
+            anyhow::Ok(())
+        });
+
+        Ok(())
+    }
+
+    fn save_single(&mut self) -> anyhow::Result<()> {
+        if let Some((record, receiver)) = self.single_picker_receiver.take() {
             match receiver.try_recv() {
                 Ok(path) => {
-                    let Some(path) = path else {
+                    let Some(mut path) = path else {
                         return Ok(());
                     };
+                    if path.extension().is_none() {
+                        path.set_extension(self.config.format.extension());
+                    }
+                    let (Some(dir), Some(filename)) = (path.parent(), path.file_name()) else {
+                        anyhow::bail!("Invalid path {}", path.display());
+                    };
 
-                    let image = fs::read(path)?;
-                    self.image = Some(
-                        RetainedImage::from_image_bytes("Template Image", &image)
-                            .expect("retained image"),
-                    );
-                    self.set_template(Arc::new(image));
+                    let template = self.ready_template().map_err(anyhow::Error::msg)?;
+                    let mut batch = self.batch(template);
+                    batch.output_dir = dir.to_path_buf();
+                    let overflows =
+                        generate_certificate(&batch, &record, &filename.to_string_lossy())?;
+
+                    self.summary = None;
+                    self.last_output_dir = Some(batch.output_dir);
+                    let t = self.config.language.strings();
+                    self.status = format!("{}: {}", fix_text(t.saved), path.display());
+                    if !overflows.is_empty() {
+                        self.status +=
+                            &format!("\n{}: {}", fix_text(t.overflows), overflows.join(", "));
+                    }
+                    self.certificates_window_open = true;
                 }
                 Err(TryRecvError::Empty) => {
-                    self.image_file_picker_receiver = Some(receiver);
+                    self.single_picker_receiver = Some((record, receiver));
                 }
                 Err(e) => {
                     panic!("{e}");
@@ -230,171 +1211,1266 @@ impl CertApp {
         Ok(())
     }
 
-    fn send_emails(&mut self) -> anyhow::Result<()> {
-        {
-            let records = self.records.clone();
-            let points = self
-                .rects
-                .iter()
-                .map(|r| {
-                    let points = r.0.min();
-                    (
-                        Point::new(points.p1.x, points.p1.y) * 2.5,
-                        (points.p1.x - points.p2.x).abs() * 2.5,
-                    )
-                })
-                .collect::<Vec<(Point, f32)>>();
-            let template = self.template.clone();
-            let email_creds = self.config.email.clone();
-            let Some(email_index) = self
-                .columns
-                .iter()
-                .position(|s| s.to_lowercase() == "email" || s == "البريد الالكتروني") else {
-                self.send_email_window_open = true;
-                self.status = String::from("No email column");
-                return Ok(());
-            };
-            let font_size = self.font_size;
+    /// Remakes the template thumbnail after the window moved to a screen with another scale
+    fn refresh_thumbnail(&mut self) {
+        let (Some(template), Some(_)) = (&self.template_bytes, &self.image) else {
+            return;
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let template = Arc::clone(template);
+        let pixels_per_point = self.pixels_per_point;
+        std::thread::spawn(move || sender.send(thumbnail(&template, pixels_per_point)));
+        self.thumbnail_receiver = Some(receiver);
+    }
 
-            self.send_email_window_open = true;
-            self.status = String::from("Sending...");
-            self.t_handle = Some(std::thread::spawn(move || {
-                records.par_iter().for_each(|record| {
-                    let filename = format!("{}-{}.png", &record[0], &record[1]);
-                    generate_certificate(
-                        record,
-                        points.clone(),
-                        template.clone(),
-                        &filename,
-                        font_size,
-                    );
-                    send_email(email_creds.clone(), &filename, &record[email_index])
-                        .expect("Send Email");
-                });
-            }));
+    fn receive_thumbnail(&mut self) {
+        if let Some(receiver) = self.thumbnail_receiver.take() {
+            match receiver.try_recv() {
+                Ok(Ok(thumbnail)) => {
+                    self.image = Some(RetainedImage::from_color_image("Template Image", thumbnail));
+                }
+                Ok(Err(e)) => warn!("Failed to refresh template thumbnail: {e:#}"),
+                Err(TryRecvError::Empty) => {
+                    self.thumbnail_receiver = Some(receiver);
+                }
+                Err(e) => {
+                    panic!("{e}");
+                }
+            }
         }
-
-        Ok(())
     }
-}
 
-impl App for CertApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        ctx.set_fonts(add_fonts());
-        egui::TopBottomPanel::bottom("BottomPanel").show(ctx, |ui| {
-            ui.set_enabled(!self.template_window_open);
-            ui.set_enabled(!self.email_window_open);
-            ui.set_enabled(!self.certificates_window_open);
-            ui.set_enabled(!self.csv_file_picker_receiver.is_some());
-            ui.set_enabled(!self.image_file_picker_receiver.is_some());
-            ui.set_enabled(!self.send_email_window_open);
-            ui.horizontal(|ui| {
-                let button = ui.add_sized([20., 30.], Button::new("Import CSV"));
-                if button.clicked() {
-                    let (sender, receiver) = std::sync::mpsc::channel();
-                    if self.csv_file_picker_receiver.is_none() {
-                        self.csv_file_picker_receiver = Some(receiver);
-                        std::thread::spawn(move || {
-                            let current_dir = std::env::current_dir()?;
+    fn import_template(&mut self) -> anyhow::Result<()> {
+        if let Some(receiver) = self.image_file_picker_receiver.take() {
+            match receiver.try_recv() {
+                Ok(pick) => {
+                    let Some((path, image)) = pick else {
+                        return Ok(());
+                    };
 
-                            let path = FileDialog::new()
-                                .set_location(&current_dir)
-                                .add_filter("CSV SpreadSheet", &["csv"])
-                                .show_open_single_file()?;
+                    let name = path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    self.template_path = Some(path);
+                    // a thumbnail still being remade is for the previous template
+                    self.thumbnail_receiver = None;
+                    let decoded = image.and_then(|image| {
+                        let decoded = decode_template(&image.bytes)?;
+                        Ok((image, decoded))
+                    });
+                    match decoded {
+                        Ok((image, decoded)) => {
+                            self.image = Some(RetainedImage::from_color_image(
+                                "Template Image",
+                                image.thumbnail,
+                            ));
+                            self.template_error = None;
+                            self.template_dimensions = decoded.dimensions();
+                            self.template_dpi = template_dpi(&image.bytes);
+                            self.template_name = name;
+                            self.template_bytes = Some(Arc::new(image.bytes));
+                            self.set_template(Some(decoded));
+                        }
+                        Err(e) => {
+                            self.image = None;
+                            self.template_bytes = None;
+                            self.template_error = Some(format!("{e:#}"));
+                            self.set_template(None);
+                        }
+                    }
+                }
+                Err(TryRecvError::Empty) => {
+                    self.image_file_picker_receiver = Some(receiver);
+                }
+                Err(e) => {
+                    panic!("{e}");
+                }
+            }
+        }
 
-                            sender.send(path)?;
+        Ok(())
+    }
 
-                            anyhow::Ok(())
-                        });
+    fn import_template_variant(&mut self) -> anyhow::Result<()> {
+        if let Some((value, receiver)) = self.templates_picker_receiver.take() {
+            match receiver.try_recv() {
+                Ok(pick) => {
+                    let Some((_, image)) = pick else {
+                        return Ok(());
+                    };
+
+                    match image.and_then(|image| decode_template(&image.bytes)) {
+                        Ok(decoded) => {
+                            self.templates_error = None;
+                            self.templates.insert(value, decoded);
+                        }
+                        Err(e) => self.templates_error = Some(format!("{value}: {e:#}")),
                     }
                 }
-                self.import_csv().expect("import csv");
-                let button = ui.add_sized([20., 30.], Button::new("Import Template"));
+                Err(TryRecvError::Empty) => {
+                    self.templates_picker_receiver = Some((value, receiver));
+                }
+                Err(e) => {
+                    panic!("{e}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn import_overlay(&mut self) {
+        if let Some(receiver) = self.overlay_picker_receiver.take() {
+            match receiver.try_recv() {
+                Ok(path) => {
+                    let Some(path) = path else {
+                        return;
+                    };
+
+                    let decoded = fs::read(&path)
+                        .with_context(|| format!("Could not read {}", path.display()))
+                        .and_then(|image| decode_template(&image));
+                    match decoded {
+                        Ok(decoded) => {
+                            self.overlay_error = None;
+                            self.images.insert(path.clone(), decoded);
+                            self.fields.push(Field {
+                                rect: TextRect::default(),
+                                color: rand::thread_rng().gen::<Wrapper<Color32>>().0,
+                                kind: FieldKind::Image(path),
+                                enabled: false,
+                                text: TextOptions::default(),
+                                fallback: String::new(),
+                            });
+                            self.current_rect = self.fields.len() - 1;
+                        }
+                        Err(e) => self.overlay_error = Some(format!("{e:#}")),
+                    }
+                }
+                Err(TryRecvError::Empty) => {
+                    self.overlay_picker_receiver = Some(receiver);
+                }
+                Err(e) => {
+                    panic!("{e}");
+                }
+            }
+        }
+    }
+
+    fn preview_selected(&mut self) -> anyhow::Result<()> {
+        self.previewed_row = None;
+        let Some(record) = self.selected_record.map(|i| self.records[i].clone()) else {
+            anyhow::bail!(fix_text(
+                self.config.language.strings().select_row_to_preview
+            ));
+        };
+
+        self.preview(&record)?;
+        self.previewed_row = self.selected_record;
+        Ok(())
+    }
+
+    /// Moves the preview `step` rows through the records, stopping at the first and last
+    fn step_preview(&mut self, step: isize) {
+        let Some(row) = self.previewed_row.filter(|row| *row < self.records.len()) else {
+            return;
+        };
+        let next = row
+            .saturating_add_signed(step)
+            .min(self.records.len().saturating_sub(1));
+        if next == row {
+            return;
+        }
+        self.selected_record = Some(next);
+        if let Err(e) = self.preview_selected() {
+            self.preview = None;
+            self.status = format!("{e:#}");
+        }
+    }
+
+    /// Previews the widest value of every column together
+    fn preview_widest(&mut self) -> anyhow::Result<()> {
+        self.previewed_row = None;
+        let template = self.ready_template().map_err(anyhow::Error::msg)?;
+        let record = widest_record(&self.batch(template), &self.records);
+
+        self.preview(&record)
+    }
+
+    fn preview(&mut self, record: &StringRecord) -> anyhow::Result<()> {
+        let template = self.ready_template().map_err(anyhow::Error::msg)?;
+        let batch = self.batch(template);
+        self.glyph_warnings = missing_glyphs(&batch, record);
+        for warning in &self.glyph_warnings {
+            warn!("{warning}");
+        }
+        let certificate = render_certificate(&batch, record)?;
+        let data = certificate
+            .image
+            .encode_to_data(EncodedImageFormat::PNG)
+            .context("Failed to encode preview")?;
+        let preview = RetainedImage::from_image_bytes("Preview", data.as_bytes())
+            .map_err(|e| anyhow::anyhow!(e))?;
+        self.preview = Some(preview);
+
+        Ok(())
+    }
+
+    /// Keeps the font size and the text options of column fields in the config for next time
+    fn save_styles(&mut self) {
+        self.config.font_size = self.font_size;
+        for field in &self.fields {
+            if let FieldKind::Column(i) = field.kind {
+                if let Some(column) = self.columns.get(i) {
+                    self.config
+                        .field_styles
+                        .insert(column.to_string(), field.text);
+                }
+            }
+        }
+        save_config(&self.config);
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            csv: self.csv_path.clone(),
+            template: self.template_path.clone(),
+            font_size: self.font_size,
+            fields: self.fields.iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Re-imports the files of `layout`, its fields are put back once they are loaded
+    fn restore_layout(&mut self, layout: Layout) {
+        // files that were moved away since are left out, instead of failing the import
+        if let Some(path) = layout.csv.clone().filter(|path| path.exists()) {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            sender.send(Some(path)).expect("send csv path");
+            self.csv_file_picker_receiver = Some(receiver);
+        }
+        if let Some(path) = layout.template.clone().filter(|path| path.exists()) {
+            self.image_file_picker_receiver = Some(pick_template(
+                Some(path),
+                self.config.pdf_dpi,
+                self.pixels_per_point,
+            ));
+        }
+        self.pending_layout = Some(layout);
+    }
+
+    fn apply_layout(&mut self, layout: Layout) {
+        let columns = self.columns.len();
+        self.fields = layout
+            .fields
+            .into_iter()
+            .map(Field::from)
+            .filter(|field| match &field.kind {
+                FieldKind::Column(i) => *i < columns,
+                FieldKind::Image(path) => self.load_image(path),
+                _ => true,
+            })
+            .collect();
+        self.font_size = layout.font_size;
+        self.current_rect = 0;
+    }
+
+    /// Decodes the image of a saved `FieldKind::Image` field, `false` if it can't be loaded
+    fn load_image(&mut self, path: &Path) -> bool {
+        let image = fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|image| decode_template(&image));
+        match image {
+            Ok(image) => {
+                self.images.insert(path.to_path_buf(), image);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to load {}: {e:#}", path.display());
+                false
+            }
+        }
+    }
+
+    fn export_mapping(&mut self) {
+        let Some(receiver) = self.mapping_export_receiver.take() else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(Some(path)) => {
+                let mapping = FieldMapping::new(
+                    &self.columns,
+                    &self.fields,
+                    &self.config.font_family,
+                    self.font_size,
+                );
+                self.mapping_status = Some(match mapping.save(&path) {
+                    Ok(()) => format!(
+                        "{}: {}",
+                        fix_text(self.config.language.strings().exported_to),
+                        path.display()
+                    ),
+                    Err(e) => format!("{e:#}"),
+                });
+            }
+            Ok(None) => {}
+            Err(TryRecvError::Empty) => self.mapping_export_receiver = Some(receiver),
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Puts an exported mapping on the current CSV, matching its columns by name
+    fn import_mapping(&mut self) {
+        let Some(receiver) = self.mapping_import_receiver.take() else {
+            return;
+        };
+        let path = match receiver.try_recv() {
+            Ok(Some(path)) => path,
+            Ok(None) => return,
+            Err(TryRecvError::Empty) => {
+                self.mapping_import_receiver = Some(receiver);
+                return;
+            }
+            Err(e) => panic!("{e}"),
+        };
+        let mapping = match FieldMapping::load(&path) {
+            Ok(mapping) => mapping,
+            Err(e) => {
+                self.mapping_status = Some(format!("{e:#}"));
+                return;
+            }
+        };
+
+        self.font_size = mapping.font_size;
+        self.config.font_family = mapping.font_family.clone();
+        self.preferences.font_family = mapping.font_family.clone();
+        let (fields, missing) = mapping.fields_for(&self.columns);
+        let mut ignored = Vec::new();
+        for field in fields {
+            if let FieldKind::Column(i) = field.kind {
+                // the CSV's own column fields take the mapped placement
+                match self
+                    .fields
+                    .iter_mut()
+                    .find(|existing| existing.kind == FieldKind::Column(i))
+                {
+                    Some(existing) => *existing = field,
+                    None => ignored.push(self.columns[i].to_string()),
+                }
+                continue;
+            }
+            if let FieldKind::Image(path) = &field.kind {
+                if !self.load_image(path) {
+                    continue;
+                }
+            }
+            self.fields.push(field);
+        }
+        self.current_rect = 0;
+        let t = self.config.language.strings();
+        let mut status = Vec::new();
+        if !missing.is_empty() {
+            status.push(format!(
+                "{}: {}",
+                fix_text(t.not_in_csv),
+                missing.join(", ")
+            ));
+        }
+        if !ignored.is_empty() {
+            status.push(format!(
+                "{}: {}",
+                fix_text(t.ignored_in_csv),
+                ignored.join(", ")
+            ));
+        }
+        self.mapping_status = Some(if status.is_empty() {
+            fix_text(t.mapping_imported)
+        } else {
+            status.join("\n")
+        });
+    }
+
+    /// Writes the layout to the autosave file if it changed since the last time
+    fn autosave(&mut self) {
+        if self.last_autosave.elapsed() < AUTOSAVE_INTERVAL || self.fields.is_empty() {
+            return;
+        }
+        self.last_autosave = Instant::now();
+
+        let layout = serde_json::to_string(&self.layout()).expect("Layout to string");
+        if layout != self.autosaved {
+            match fs::write(autosave_path(), &layout) {
+                Ok(()) => self.autosaved = layout,
+                Err(e) => warn!("Failed to autosave layout: {e}"),
+            }
+        }
+    }
+
+    /// Sends every row its certificate, `confirmed` is the output directory the user agreed to
+    fn send_emails(
+        &mut self,
+        duplicates: Option<Duplicates>,
+        confirmed: Option<PathBuf>,
+    ) -> anyhow::Result<()> {
+        {
+            let config = self.config.clone();
+            let Some(email_index) = self.email_column() else {
+                self.send_email_window_open = true;
+                self.status = fix_text(self.config.language.strings().no_email_column);
+                return Ok(());
+            };
+
+            self.send_email_window_open = true;
+            let template = match self.ready_template() {
+                Ok(template) => template,
+                Err(e) => {
+                    self.status = e;
+                    return Ok(());
+                }
+            };
+            let sent_log_path = dirs::config_dir()
+                .expect("config directory")
+                .join("certs/sent")
+                .join(format!("{}.txt", self.template_name));
+            let sent_log = match SentLog::open(&sent_log_path) {
+                Ok(sent_log) => sent_log,
+                Err(e) => {
+                    self.status = format!("{e:#}");
+                    return Ok(());
+                }
+            };
+            let rows = self
+                .records
+                .iter()
+                .enumerate()
+                .filter(|(_, record)| !(self.skip_sent && sent_log.contains(&record[email_index])))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            let output_dir = confirmed
+                .clone()
+                .unwrap_or_else(|| self.config.output_dir.clone());
+            let (mut jobs, mut shortened) = self.jobs(&rows, &output_dir);
+            // sending regenerates every certificate, a previous batch in the way is kept and this
+            // one goes into a folder of its own
+            let output_dir = match confirmed {
+                Some(_) => output_dir,
+                None if jobs
+                    .iter()
+                    .any(|(_, _, filename)| output_dir.join(filename).exists()) =>
+                {
+                    let output_dir = run_folder(&output_dir);
+                    (jobs, shortened) = self.jobs(&rows, &output_dir);
+                    output_dir
+                }
+                None => output_dir,
+            };
+            let mut counts = BTreeMap::<String, usize>::new();
+            for (_, record, _) in &jobs {
+                *counts
+                    .entry(normalize_address(&record[email_index]))
+                    .or_default() += 1;
+            }
+            counts.retain(|_, count| *count > 1);
+            match duplicates {
+                _ if counts.is_empty() => {}
+                None => {
+                    self.duplicates = counts.into_iter().collect();
+                    self.send_email_window_open = false;
+                    self.duplicates_window_open = true;
+                    return Ok(());
+                }
+                Some(Duplicates::SendAll) => {}
+                Some(Duplicates::SendOnce) => {
+                    let mut seen = HashSet::new();
+                    jobs.retain(|(_, record, _)| {
+                        seen.insert(normalize_address(&record[email_index]))
+                    });
+                }
+            }
+            if confirmed.is_none() {
+                self.send_email_window_open = false;
+                self.send_confirmation = Some(SendConfirmation {
+                    duplicates,
+                    recipients: jobs.len(),
+                    email_column: self.columns[email_index].to_string(),
+                    output_dir,
+                });
+                return Ok(());
+            }
+            self.status = fix_text(self.config.language.strings().sending);
+            self.shortened = shortened;
+            self.last_output_dir = Some(output_dir.clone());
+            // failures of the last generation run are out of date once a send regenerates the rows
+            self.failed_rows
+                .lock()
+                .expect("failed rows lock")
+                .rows
+                .clear();
+            let statuses = Arc::clone(&self.send_statuses);
+            {
+                let mut statuses = statuses.lock().expect("send status lock");
+                *statuses = vec![None; self.records.len()];
+                for (i, ..) in &jobs {
+                    statuses[*i] = Some(SendStatus::Pending);
+                }
+            }
+            let mut batch = self.batch(template);
+            batch.output_dir = output_dir;
+            let threads = self.config.threads;
+            let sending = Throttle::new(self.config.send_threads);
+            let queue = Arc::clone(&self.email_queue);
+            let overflows = Arc::clone(&self.overflows);
+            overflows.lock().expect("overflow log lock").clear();
+            self.run_started = Some((Instant::now(), jobs.len()));
+            self.summary = None;
+            self.t_handle = Some(std::thread::spawn(move || {
+                in_pool(threads, || {
+                    let mut failures = jobs
+                        .par_iter()
+                        .filter_map(|(i, record, filename)| {
+                            let to = record[email_index].trim();
+                            let attachment =
+                                attachment_name(&config, &batch.columns, record, filename);
+                            let body = email_body(&config, &batch.columns, record);
+                            let result = generate_certificate(&batch, record, filename)
+                                .map(|fields| note_overflows(&overflows, filename, fields))
+                                .and_then(|()| {
+                                    let file = batch.output_dir.join(filename);
+                                    let file = fs::canonicalize(&file).unwrap_or(file);
+                                    sending
+                                        .run(|| send_email(&config, &file, &attachment, &body, to))
+                                        .map_err(|e| {
+                                            let email = QueuedEmail {
+                                                to: to.to_string(),
+                                                file,
+                                                attachment_name: attachment.clone(),
+                                                body: body.clone(),
+                                                sent_log: sent_log_path.clone(),
+                                                error: format!("{e:#}"),
+                                            };
+                                            queue
+                                                .lock()
+                                                .expect("email queue lock")
+                                                .emails
+                                                .push(email);
+                                            e
+                                        })
+                                })
+                                .and_then(|()| sent_log.add(to));
+                            let status = match result {
+                                Ok(()) => SendStatus::Sent,
+                                Err(_) => SendStatus::Failed,
+                            };
+                            if let Some(row) =
+                                statuses.lock().expect("send status lock").get_mut(*i)
+                            {
+                                *row = Some(status);
+                            }
+                            result.err().map(|e| format!("{to}: {e:#}"))
+                        })
+                        .collect();
+                    save_queue(&queue, &mut failures);
+                    failures
+                })
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Sends the queued emails again, the ones that fail stay queued
+    fn retry_queued(&mut self) {
+        let emails = std::mem::take(&mut self.email_queue.lock().expect("email queue lock").emails);
+        let mut sent_logs = HashMap::new();
+        for email in &emails {
+            if sent_logs.contains_key(&email.sent_log) {
+                continue;
+            }
+            match SentLog::open(&email.sent_log) {
+                Ok(sent_log) => {
+                    sent_logs.insert(email.sent_log.clone(), sent_log);
+                }
+                Err(e) => warn!("{e:#}"),
+            }
+        }
+        // addresses that went out some other way since they were queued aren't sent twice
+        let emails = emails
+            .into_iter()
+            .filter(|email| {
+                !sent_logs
+                    .get(&email.sent_log)
+                    .is_some_and(|sent_log| sent_log.contains(&email.to))
+            })
+            .collect::<Vec<_>>();
+
+        self.send_email_window_open = true;
+        self.status = fix_text(self.config.language.strings().sending);
+        self.failed_rows
+            .lock()
+            .expect("failed rows lock")
+            .rows
+            .clear();
+        let config = self.config.clone();
+        let threads = self.config.threads;
+        let sending = Throttle::new(self.config.send_threads);
+        let queue = Arc::clone(&self.email_queue);
+        self.run_started = Some((Instant::now(), emails.len()));
+        self.summary = None;
+        self.t_handle = Some(std::thread::spawn(move || {
+            in_pool(threads, || {
+                let mut failures = emails
+                    .into_par_iter()
+                    .filter_map(|mut email| {
+                        let to = email.to.clone();
+                        let sent = sending.run(|| {
+                            send_email(
+                                &config,
+                                &email.file,
+                                &email.attachment_name,
+                                &email.body,
+                                &to,
+                            )
+                        });
+                        match sent {
+                            Ok(()) => sent_logs
+                                .get(&email.sent_log)
+                                .map_or(Ok(()), |sent_log| sent_log.add(&to))
+                                .err()
+                                .map(|e| format!("{to}: {e:#}")),
+                            Err(e) => {
+                                email.error = format!("{e:#}");
+                                queue.lock().expect("email queue lock").emails.push(email);
+                                Some(format!("{to}: {e:#}"))
+                            }
+                        }
+                    })
+                    .collect();
+                save_queue(&queue, &mut failures);
+                failures
+            })
+        }));
+    }
+}
+
+impl App for CertApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_styles();
+        if self.restore.is_none() {
+            if let Err(e) = fs::remove_file(autosave_path()) {
+                debug!("No autosave to remove: {e}");
+            }
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let t = self.config.language.strings();
+        let column_hint = format!("{} {{column}}", fix_text(t.column_hint));
+        if ctx.pixels_per_point() != self.pixels_per_point {
+            self.pixels_per_point = ctx.pixels_per_point();
+            self.refresh_thumbnail();
+        }
+        self.receive_thumbnail();
+        let any_modal_open = self.template_window_open
+            || self.templates_window_open
+            || self.preview_window_open
+            || self.preferences_window_open
+            || self.certificates_window_open
+            || self.send_email_window_open
+            || self.existing_files_window_open
+            || self.duplicates_window_open
+            || self.send_confirmation.is_some()
+            || self.restore.is_some()
+            || self.csv_file_picker_receiver.is_some()
+            || self.image_file_picker_receiver.is_some();
+        egui::TopBottomPanel::bottom("BottomPanel").show(ctx, |ui| {
+            ui.set_enabled(!any_modal_open);
+            ui.with_layout(row_layout(self.config.language), |ui| {
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.import_csv)));
                 if button.clicked() {
                     let (sender, receiver) = std::sync::mpsc::channel();
-                    if self.image_file_picker_receiver.is_none() {
+                    if self.csv_file_picker_receiver.is_none() {
+                        self.csv_file_picker_receiver = Some(receiver);
                         std::thread::spawn(move || {
                             let current_dir = std::env::current_dir()?;
 
                             let path = FileDialog::new()
                                 .set_location(&current_dir)
-                                .add_filter("Template Image", &["jpg", "png", "jpeg"])
+                                .add_filter("CSV SpreadSheet", &["csv"])
+                                .add_filter("Excel Workbook", &["xlsx"])
                                 .show_open_single_file()?;
 
-                            sender.send(path.clone())?;
+                            sender.send(path)?;
+
                             anyhow::Ok(())
                         });
-                        self.image_file_picker_receiver = Some(receiver);
                     }
                 }
+                if let Err(e) = self.import_csv() {
+                    self.csv_file_picker_receiver = None;
+                    self.import_error = Some(format!("{e:#}"));
+                }
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.import_template)));
+                if button.clicked() && self.image_file_picker_receiver.is_none() {
+                    self.image_file_picker_receiver = Some(pick_template(
+                        None,
+                        self.config.pdf_dpi,
+                        self.pixels_per_point,
+                    ));
+                }
                 self.import_template().expect("pick template");
-                let button = ui.add_sized([20., 30.], Button::new("Template Layout"));
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.template_layout)));
                 if button.clicked() {
                     self.template_window_open = true;
                 }
-                let button = ui.add_sized([20., 30.], Button::new("Create"));
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.templates)));
+                if button.clicked() {
+                    self.templates_window_open = true;
+                }
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.preview_selected)));
                 if button.clicked() {
-                    self.generate_certificates().expect("certificates");
+                    self.preview_window_open = true;
+                    if let Err(e) = self.preview_selected() {
+                        self.preview = None;
+                        self.status = format!("{e:#}");
+                    }
+                }
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.preview_widest)));
+                if button
+                    .on_hover_text(fix_text(t.preview_widest_hint))
+                    .clicked()
+                {
+                    self.preview_window_open = true;
+                    if let Err(e) = self.preview_widest() {
+                        self.preview = None;
+                        self.status = format!("{e:#}");
+                    }
                 }
-                #[cfg(not(feature = "baba"))]
+                let button = ui.add_enabled(
+                    self.selected_record.is_some() && self.single_picker_receiver.is_none(),
+                    Button::new(fix_text(t.generate_selected)),
+                );
+                if button
+                    .on_hover_text(fix_text(t.generate_selected_hint))
+                    .clicked()
                 {
-                    let button = ui.add_sized([20., 30.], Button::new("Email Credentials"));
-                    if button.clicked() {
-                        self.email_window_open = true;
+                    if let Err(e) = self.pick_single() {
+                        self.status = format!("{e:#}");
                     }
                 }
-                let button = ui.add_sized([20., 30.], Button::new("Send Email"));
+                if let Err(e) = self.save_single() {
+                    self.status = format!("{e:#}");
+                    self.certificates_window_open = true;
+                }
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.create)));
+                if button.clicked() {
+                    self.generate_certificates(None, None)
+                        .expect("certificates");
+                }
+                let button = ui.add_enabled(
+                    self.summary.is_some(),
+                    Button::new(fix_text(t.last_results)).min_size(Vec2::new(20., 30.)),
+                );
+                if button.clicked() {
+                    self.certificates_window_open = true;
+                }
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.preferences)));
                 if button.clicked() {
-                    println!("Send Email");
-                    self.send_emails().expect("Send Emails");
+                    self.preferences = self.config.clone();
+                    self.preferences_window_open = true;
+                }
+                let button = ui.add_sized([20., 30.], Button::new(fix_text(t.send_email)));
+                if button.clicked() {
+                    self.send_emails(None, None).expect("Send Emails");
+                }
+                let queued = self
+                    .email_queue
+                    .lock()
+                    .expect("email queue lock")
+                    .emails
+                    .len();
+                let button = ui.add_enabled(
+                    queued > 0 && self.t_handle.is_none(),
+                    Button::new(format!("{} ({queued})", fix_text(t.retry_queued))),
+                );
+                if button
+                    .on_hover_text(fix_text(t.retry_queued_hint))
+                    .clicked()
+                {
+                    self.retry_queued();
+                }
+                ui.checkbox(&mut self.skip_sent, fix_text(t.skip_sent))
+                    .on_hover_text(fix_text(t.skip_sent_hint));
+                ui.add(
+                    egui::Slider::new(&mut self.font_size, 0.0..=100.).text(fix_text(t.font_size)),
+                )
+            });
+            ui.with_layout(row_layout(self.config.language), |ui| {
+                let mut serials = self.serials;
+                egui::ComboBox::from_label(fix_text(t.serial_column))
+                    .selected_text(match serials {
+                        None => fix_text(t.none),
+                        Some(SerialKind::Sequential) => fix_text(t.sequential),
+                        Some(SerialKind::Uuid) => fix_text(t.uuid),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut serials, None, fix_text(t.none));
+                        ui.selectable_value(
+                            &mut serials,
+                            Some(SerialKind::Sequential),
+                            fix_text(t.sequential),
+                        );
+                        ui.selectable_value(&mut serials, Some(SerialKind::Uuid), fix_text(t.uuid));
+                    })
+                    .response
+                    .on_hover_text(format!("{}: {{{SERIAL_COLUMN}}}", fix_text(t.serial_hint)));
+                if serials != self.serials {
+                    self.set_serials(serials);
+                }
+                ui.separator();
+                egui::ComboBox::from_label(fix_text(t.subfolder_by))
+                    .selected_text(match self.group_column {
+                        Some(i) => fix_text(&self.columns[i]),
+                        None => fix_text(t.none),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.group_column, None, fix_text(t.none));
+                        for (i, column) in self.columns.iter().enumerate() {
+                            ui.selectable_value(&mut self.group_column, Some(i), fix_text(column));
+                        }
+                    });
+                ui.separator();
+                ui.label(fix_text(t.file_name));
+                for (n, name_column) in self.name_columns.iter_mut().enumerate() {
+                    egui::ComboBox::from_id_source(("name_column", n))
+                        .selected_text(
+                            self.columns
+                                .get(*name_column)
+                                .map_or(String::new(), fix_text),
+                        )
+                        .show_ui(ui, |ui| {
+                            for (i, column) in self.columns.iter().enumerate() {
+                                ui.selectable_value(name_column, i, fix_text(column));
+                            }
+                        });
                 }
-                ui.add(egui::Slider::new(&mut self.font_size, 0.0..=100.).text("Font size"))
+                ui.separator();
+                ui.checkbox(&mut self.first_row_is_data, fix_text(t.first_row_is_data))
+                    .on_hover_text(fix_text(t.first_row_hint));
+                ui.separator();
+                let mut row_filter = self.row_filter.clone();
+                egui::ComboBox::from_label(fix_text(t.drop_rows))
+                    .selected_text(match row_filter {
+                        RowFilter::KeepAll => fix_text(t.never),
+                        RowFilter::RequireAll => fix_text(t.any_empty),
+                        RowFilter::Required(_) => fix_text(t.required_empty),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut row_filter, RowFilter::KeepAll, fix_text(t.never));
+                        ui.selectable_value(
+                            &mut row_filter,
+                            RowFilter::RequireAll,
+                            fix_text(t.any_empty),
+                        );
+                        if ui
+                            .selectable_label(
+                                matches!(row_filter, RowFilter::Required(_)),
+                                fix_text(t.required_empty),
+                            )
+                            .clicked()
+                            && !matches!(row_filter, RowFilter::Required(_))
+                        {
+                            row_filter = RowFilter::Required(BTreeSet::new());
+                        }
+                    });
+                if let RowFilter::Required(required) = &mut row_filter {
+                    let csv_columns = self.columns.len() - usize::from(self.serials.is_some());
+                    ui.menu_button(fix_text(t.required_columns), |ui| {
+                        for (i, column) in self.columns.iter().take(csv_columns).enumerate() {
+                            let mut checked = required.contains(&i);
+                            if ui.checkbox(&mut checked, fix_text(column)).changed() {
+                                if checked {
+                                    required.insert(i);
+                                } else {
+                                    required.remove(&i);
+                                }
+                            }
+                        }
+                    });
+                }
+                if row_filter != self.row_filter {
+                    self.row_filter = row_filter;
+                    self.filter_records();
+                }
+                ui.separator();
+                ui.checkbox(&mut self.fixed_page_size, fix_text(t.fixed_page_size));
+                ui.add_enabled_ui(self.fixed_page_size, |ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut self.page_size.width)
+                            .clamp_range(1..=20000)
+                            .suffix(" px"),
+                    );
+                    ui.label("x");
+                    ui.add(
+                        egui::DragValue::new(&mut self.page_size.height)
+                            .clamp_range(1..=20000)
+                            .suffix(" px"),
+                    );
+                    let fit_label = |fit| match fit {
+                        FitMode::Stretch => fix_text(t.stretch),
+                        FitMode::Contain => fix_text(t.contain),
+                        FitMode::Cover => fix_text(t.cover),
+                    };
+                    egui::ComboBox::from_label(fix_text(t.fit))
+                        .selected_text(fit_label(self.page_size.fit))
+                        .show_ui(ui, |ui| {
+                            for fit in [FitMode::Stretch, FitMode::Contain, FitMode::Cover] {
+                                ui.selectable_value(&mut self.page_size.fit, fit, fit_label(fit));
+                            }
+                        });
+                });
             });
+            if let Some(stats) = &self.import_stats {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}: {}", fix_text(t.records), self.records.len()));
+                    if stats.incomplete > 0 {
+                        ui.separator();
+                        ui.label(format!(
+                            "{}: {}",
+                            fix_text(t.dropped_rows),
+                            stats.incomplete
+                        ));
+                    }
+                    if stats.malformed > 0 {
+                        ui.separator();
+                        ui.label(format!(
+                            "{}: {}",
+                            fix_text(t.unreadable_rows),
+                            stats.malformed
+                        ));
+                    }
+                    if let Some(valid_emails) = stats.valid_emails {
+                        ui.separator();
+                        ui.label(format!("{}: {valid_emails}", fix_text(t.valid_emails)));
+                    }
+                });
+            }
+            if let Some(error) = &self.import_error {
+                ui.colored_label(Color32::RED, error);
+            }
             ui.set_min_size(Vec2::new(ui.available_height(), 20.));
         });
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.set_enabled(!self.email_window_open);
-            ui.set_enabled(!self.send_email_window_open);
-            ui.set_enabled(!self.certificates_window_open);
-            ui.set_enabled(!self.template_window_open);
+            ui.set_enabled(!any_modal_open);
             self.table(ui);
         });
 
-        egui::Window::new("Draw Areas")
+        self.import_overlay();
+        let mut refilter = false;
+        egui::Window::new(fix_text(t.draw_areas))
             .open(&mut self.template_window_open)
             .anchor(Align2::CENTER_CENTER, [0., 0.])
             .resizable(false)
             .collapsible(false)
             .show(ctx, |ui| {
-                if self.rects.len() < 1 {
-                    ui.label("Add a CSV file");
+                if self.columns.is_empty() {
+                    ui.label(fix_text(t.add_csv));
+                    return;
+                }
+                ui.menu_button(fix_text(t.ignored_columns), |ui| {
+                    for (i, column) in self.columns.iter().enumerate() {
+                        // an ignored column is one without a field
+                        let position = self
+                            .fields
+                            .iter()
+                            .position(|field| field.kind == FieldKind::Column(i));
+                        let mut ignored = position.is_none();
+                        if !ui.checkbox(&mut ignored, fix_text(column)).changed() {
+                            continue;
+                        }
+                        match position {
+                            Some(position) => {
+                                self.fields.remove(position);
+                                self.current_rect = 0;
+                            }
+                            None => self.fields.push(Field {
+                                rect: TextRect::default(),
+                                color: rand::thread_rng().gen::<Wrapper<Color32>>().0,
+                                kind: FieldKind::Column(i),
+                                enabled: false,
+                                text: TextOptions::default(),
+                                fallback: String::new(),
+                            }),
+                        }
+                    }
+                });
+                if self.fields.is_empty() {
                     return;
                 }
                 let Some(template) = &self.image else {
-                    ui.label("Choose a template");
+                    match &self.template_error {
+                        Some(error) => {
+                            ui.label(format!("{}: {error}", fix_text(t.invalid_template)))
+                        }
+                        None => ui.label(fix_text(t.choose_template)),
+                    };
                     return;
                 };
-                let (current, current_color) = &mut self.rects[self.current_rect];
-
-                ui.label(
-                    RichText::new(format!(
-                        "Column: {}",
-                        fix_text(&self.columns[self.current_rect])
-                    ))
-                    .color(*current_color),
-                );
+                // Tab / Shift+Tab cycle the active field, unless a text box has focus
+                if !ctx.wants_keyboard_input() {
+                    let len = self.fields.len();
+                    let mut input = ctx.input_mut();
+                    if input.consume_key(Modifiers::SHIFT, Key::Tab) {
+                        self.current_rect = (self.current_rect + len - 1) % len;
+                    } else if input.consume_key(Modifiers::NONE, Key::Tab) {
+                        self.current_rect = (self.current_rect + 1) % len;
+                    }
+                }
+                let Field {
+                    rect: current,
+                    color: current_color,
+                    kind: current_kind,
+                    enabled: current_enabled,
+                    text: current_text,
+                    fallback: current_fallback,
+                } = &mut self.fields[self.current_rect];
 
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(field_label(t, &self.columns, current_kind))
+                            .color(*current_color),
+                    );
+                    ui.checkbox(current_enabled, fix_text(t.draw));
+                    ui.separator();
+                    let ISize { width, height } = self.template_dimensions;
+                    let template = fix_text(t.template);
+                    match self.template_dpi {
+                        Some((x, y)) if x == y => {
+                            ui.label(format!("{template}: {width} x {height} px, {x:.0} DPI"))
+                        }
+                        Some((x, y)) => ui.label(format!(
+                            "{template}: {width} x {height} px, {x:.0} x {y:.0} DPI"
+                        )),
+                        None => ui.label(format!("{template}: {width} x {height} px")),
+                    };
+                });
+                if matches!(
+                    current_kind,
+                    FieldKind::Column(_)
+                        | FieldKind::Static { .. }
+                        | FieldKind::Combined(_)
+                        | FieldKind::Date { .. }
+                ) {
+                    ui.horizontal(|ui| {
+                        let mut custom_height = current_text.line_height.is_some();
+                        ui.checkbox(&mut custom_height, fix_text(t.line_height));
+                        match (custom_height, &mut current_text.line_height) {
+                            (true, Some(line_height)) => {
+                                ui.add(
+                                    egui::DragValue::new(line_height)
+                                        .speed(0.05)
+                                        .clamp_range(0.5..=4.),
+                                );
+                            }
+                            (true, line_height) => *line_height = Some(1.2),
+                            (false, line_height) => *line_height = None,
+                        }
+                        ui.separator();
+                        let mut limit_lines = current_text.max_lines.is_some();
+                        ui.checkbox(&mut limit_lines, fix_text(t.max_lines));
+                        match (limit_lines, &mut current_text.max_lines) {
+                            (true, Some(max_lines)) => {
+                                ui.add(egui::DragValue::new(max_lines).clamp_range(1..=20));
+                            }
+                            (true, max_lines) => *max_lines = Some(1),
+                            (false, max_lines) => *max_lines = None,
+                        }
+                        let mut limit_chars = current_text.max_chars.is_some();
+                        ui.checkbox(&mut limit_chars, fix_text(t.max_chars));
+                        match (limit_chars, &mut current_text.max_chars) {
+                            (true, Some(max_chars)) => {
+                                ui.add(egui::DragValue::new(max_chars).clamp_range(1..=1000));
+                            }
+                            (true, max_chars) => *max_chars = Some(40),
+                            (false, max_chars) => *max_chars = None,
+                        }
+                        ui.separator();
+                        ui.label(fix_text(t.letter_spacing));
+                        ui.add(
+                            egui::DragValue::new(&mut current_text.letter_spacing)
+                                .speed(0.1)
+                                .suffix(" px"),
+                        );
+                        ui.label(fix_text(t.word_spacing));
+                        ui.add(
+                            egui::DragValue::new(&mut current_text.word_spacing)
+                                .speed(0.1)
+                                .suffix(" px"),
+                        );
+                        ui.label(fix_text(t.padding));
+                        ui.add(
+                            egui::DragValue::new(&mut current_text.padding)
+                                .speed(0.5)
+                                .clamp_range(0.0..=200.)
+                                .suffix(" px"),
+                        );
+                        ui.separator();
+                        let align_label = |align| match align {
+                            VerticalAlign::Top => fix_text(t.top),
+                            VerticalAlign::Middle => fix_text(t.middle),
+                            VerticalAlign::Bottom => fix_text(t.bottom),
+                        };
+                        ui.add_enabled_ui(current_text.anchor == TextAnchor::Top, |ui| {
+                            egui::ComboBox::from_label(fix_text(t.vertical_align))
+                                .selected_text(align_label(current_text.vertical_align))
+                                .show_ui(ui, |ui| {
+                                    for align in [
+                                        VerticalAlign::Top,
+                                        VerticalAlign::Middle,
+                                        VerticalAlign::Bottom,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut current_text.vertical_align,
+                                            align,
+                                            align_label(align),
+                                        );
+                                    }
+                                });
+                        });
+                        let anchor_label = |anchor| match anchor {
+                            TextAnchor::Top => fix_text(t.top),
+                            TextAnchor::Center => fix_text(t.center),
+                            TextAnchor::Baseline => fix_text(t.baseline),
+                        };
+                        egui::ComboBox::from_label(fix_text(t.anchor))
+                            .selected_text(anchor_label(current_text.anchor))
+                            .show_ui(ui, |ui| {
+                                for anchor in
+                                    [TextAnchor::Top, TextAnchor::Center, TextAnchor::Baseline]
+                                {
+                                    ui.selectable_value(
+                                        &mut current_text.anchor,
+                                        anchor,
+                                        anchor_label(anchor),
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text(fix_text(t.baseline_hint));
+                    });
+                }
+                if let Some(error) = &self.overlay_error {
+                    ui.label(format!("{}: {error}", fix_text(t.invalid_image)));
+                }
+                match current_kind {
+                    FieldKind::Column(_) => {
+                        ui.horizontal(|ui| {
+                            ui.label(fix_text(t.fallback));
+                            refilter = ui
+                                .text_edit_singleline(current_fallback)
+                                .on_hover_text(fix_text(t.fallback_hint))
+                                .changed();
+                        });
+                    }
+                    FieldKind::Image(_) => {}
+                    FieldKind::QrCode(data) => {
+                        ui.horizontal(|ui| {
+                            ui.label(fix_text(t.qr_data));
+                            ui.text_edit_singleline(data)
+                                .on_hover_text(column_hint.clone());
+                        });
+                    }
+                    FieldKind::Barcode(symbology, data) => {
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_label(fix_text(t.barcode_data))
+                                .selected_text(format!("{symbology:?}"))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(symbology, Symbology::Code128, "Code128");
+                                    ui.selectable_value(symbology, Symbology::Code39, "Code39");
+                                    ui.selectable_value(symbology, Symbology::Ean13, "EAN-13");
+                                });
+                            ui.text_edit_singleline(data)
+                                .on_hover_text(column_hint.clone());
+                        });
+                    }
+                    FieldKind::Combined(template) => {
+                        ui.horizontal(|ui| {
+                            ui.label(fix_text(t.text));
+                            ui.text_edit_singleline(template)
+                                .on_hover_text(column_hint.clone());
+                        });
+                    }
+                    FieldKind::Static {
+                        text,
+                        font_family,
+                        font_size,
+                    } => {
+                        ui.horizontal(|ui| {
+                            ui.label(fix_text(t.text));
+                            ui.text_edit_singleline(text);
+                            ui.label(fix_text(t.font));
+                            ui.text_edit_singleline(font_family);
+                            ui.add(
+                                egui::Slider::new(font_size, 0.0..=100.)
+                                    .text(fix_text(t.font_size)),
+                            );
+                        });
+                    }
+                    FieldKind::Date {
+                        format,
+                        column,
+                        locale,
+                    } => {
+                        ui.horizontal(|ui| {
+                            ui.label(fix_text(t.date_format));
+                            ui.text_edit_singleline(format)
+                                .on_hover_text(format!("{}: %d %B %Y", fix_text(t.example)));
+                            egui::ComboBox::from_label(fix_text(t.date_column))
+                                .selected_text(match column.and_then(|i| self.columns.get(i)) {
+                                    Some(name) => fix_text(name),
+                                    None => fix_text(t.today),
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(column, None, fix_text(t.today));
+                                    for (i, name) in self.columns.iter().enumerate() {
+                                        ui.selectable_value(column, Some(i), fix_text(name));
+                                    }
+                                });
+                            egui::ComboBox::from_label(fix_text(t.language))
+                                .selected_text(fix_text(locale.name()))
+                                .show_ui(ui, |ui| {
+                                    for language in [Language::English, Language::Arabic] {
+                                        ui.selectable_value(
+                                            locale,
+                                            language,
+                                            fix_text(language.name()),
+                                        );
+                                    }
+                                });
+                        });
+                        if let Err(e) =
+                            format_date(chrono::Local::now().date_naive(), format, *locale)
+                        {
+                            ui.colored_label(Color32::RED, fix_text(t.invalid_date_format))
+                                .on_hover_text(format!("{e:#}"));
+                        }
+                    }
+                }
+
+                let ISize { width, height } = self.template_dimensions;
                 let image = egui::Image::new(
                     template.texture_id(ctx),
-                    Vec2::new(template.size_vec2().x / 2.5, template.size_vec2().y / 2.5),
+                    Vec2::new(width as f32, height as f32) / PREVIEW_SCALE,
                 )
                 .sense(Sense::drag());
                 let image_res = ui.add(image);
 
+                // pointer positions and field rects are in points relative to the thumbnail,
+                // which is 1/PREVIEW_SCALE of the template in points at any pixels per point
                 let offset = image_res.rect.min.to_vec2();
 
                 if image_res.drag_started() {
                     if let Some(position) = image_res.interact_pointer_pos() {
+                        *current_enabled = true;
                         current.p1 = position - offset;
                     }
                 }
@@ -403,91 +2479,869 @@ impl App for CertApp {
                     current.p2 = position - offset;
                 }
 
-                for rect in &self.rects {
+                for (i, field) in self.fields.iter().enumerate() {
+                    let rect = Rect {
+                        max: (field.rect.p1.max(field.rect.p2) + offset),
+                        min: (field.rect.p1.min(field.rect.p2) + offset),
+                    };
+                    if i != self.current_rect {
+                        // disabled fields keep their place but are drawn faintly
+                        let width = if field.enabled { 3. } else { 1. };
+                        ui.painter().rect(
+                            rect,
+                            Rounding::none(),
+                            Color32::TRANSPARENT,
+                            Stroke::new(width, field.color),
+                        );
+                        continue;
+                    }
+
                     ui.painter().rect(
-                        Rect {
-                            max: (rect.0.p1.max(rect.0.p2) + offset),
-                            min: (rect.0.p1.min(rect.0.p2) + offset),
-                        },
+                        rect,
                         Rounding::none(),
-                        Color32::TRANSPARENT,
-                        Stroke::new(3., rect.1),
+                        field.color.linear_multiply(0.25),
+                        Stroke::new(6., field.color),
+                    );
+                    ui.painter().text(
+                        rect.left_top(),
+                        Align2::LEFT_BOTTOM,
+                        field_label(t, &self.columns, &field.kind),
+                        FontId::proportional(16.),
+                        field.color,
                     );
                 }
+                if let Some(position) = image_res.interact_pointer_pos() {
+                    // where the box lands on the template, to match coordinates from a spec
+                    let rect = self.fields[self.current_rect].rect.min();
+                    let readout = format!(
+                        "{:.0}, {:.0}  {:.0} x {:.0}",
+                        rect.p1.x * PREVIEW_SCALE,
+                        rect.p1.y * PREVIEW_SCALE,
+                        (rect.p2.x - rect.p1.x) * PREVIEW_SCALE,
+                        (rect.p2.y - rect.p1.y) * PREVIEW_SCALE,
+                    );
+                    let painter = ui.painter();
+                    let galley =
+                        painter.layout_no_wrap(readout, FontId::monospace(14.), Color32::WHITE);
+                    let position = position + Vec2::new(16., 16.);
+                    painter.rect_filled(
+                        Rect::from_min_size(position, galley.size()).expand(4.),
+                        Rounding::same(4.),
+                        Color32::from_black_alpha(200),
+                    );
+                    painter.galley(position, galley);
+                }
                 ui.horizontal(|ui| {
-                    if ui.button("Clear").clicked() {
-                        self.rects[self.current_rect].0 = TextRect::default();
+                    if ui.button(fix_text(t.clear)).clicked() {
+                        self.fields[self.current_rect].rect = TextRect::default();
+                        self.fields[self.current_rect].enabled = false;
+                    }
+                    if !matches!(self.fields[self.current_rect].kind, FieldKind::Column(_))
+                        && ui.button(fix_text(t.remove)).clicked()
+                    {
+                        self.fields.remove(self.current_rect);
+                        self.current_rect = 0;
+                    }
+                    if ui.button(fix_text(t.add_qr_code)).clicked() {
+                        self.fields.push(Field {
+                            rect: TextRect::default(),
+                            color: rand::thread_rng().gen::<Wrapper<Color32>>().0,
+                            kind: FieldKind::QrCode(String::new()),
+                            enabled: false,
+                            text: TextOptions::default(),
+                            fallback: String::new(),
+                        });
+                        self.current_rect = self.fields.len() - 1;
+                    }
+                    if ui.button(fix_text(t.add_barcode)).clicked() {
+                        self.fields.push(Field {
+                            rect: TextRect::default(),
+                            color: rand::thread_rng().gen::<Wrapper<Color32>>().0,
+                            kind: FieldKind::Barcode(Symbology::default(), String::new()),
+                            enabled: false,
+                            text: TextOptions::default(),
+                            fallback: String::new(),
+                        });
+                        self.current_rect = self.fields.len() - 1;
                     }
+                    if ui.button(fix_text(t.add_image)).clicked()
+                        && self.overlay_picker_receiver.is_none()
+                    {
+                        let (sender, receiver) = std::sync::mpsc::channel();
+                        self.overlay_picker_receiver = Some(receiver);
+                        std::thread::spawn(move || {
+                            let current_dir = std::env::current_dir()?;
 
-                    for (i, column) in self.columns.iter().enumerate() {
-                        if ui.button(fix_text(column)).clicked() {
+                            let path = FileDialog::new()
+                                .set_location(&current_dir)
+                                .add_filter("Image", &["jpg", "png", "jpeg", "webp"])
+                                .show_open_single_file()?;
+
+                            sender.send(path)?;
+                            anyhow::Ok(())
+                        });
+                    }
+                    if ui.button(fix_text(t.add_text)).clicked() {
+                        self.fields.push(Field {
+                            rect: TextRect::default(),
+                            color: rand::thread_rng().gen::<Wrapper<Color32>>().0,
+                            kind: FieldKind::Static {
+                                text: String::new(),
+                                font_family: self.config.font_family.clone(),
+                                font_size: self.font_size,
+                            },
+                            enabled: false,
+                            text: TextOptions::default(),
+                            fallback: String::new(),
+                        });
+                        self.current_rect = self.fields.len() - 1;
+                    }
+                    if ui.button(fix_text(t.add_combined)).clicked() {
+                        self.fields.push(Field {
+                            rect: TextRect::default(),
+                            color: rand::thread_rng().gen::<Wrapper<Color32>>().0,
+                            kind: FieldKind::Combined(String::new()),
+                            enabled: false,
+                            text: TextOptions::default(),
+                            fallback: String::new(),
+                        });
+                        self.current_rect = self.fields.len() - 1;
+                    }
+                    if ui.button(fix_text(t.add_date)).clicked() {
+                        self.fields.push(Field {
+                            rect: TextRect::default(),
+                            color: rand::thread_rng().gen::<Wrapper<Color32>>().0,
+                            kind: FieldKind::Date {
+                                format: String::from("%d %B %Y"),
+                                column: None,
+                                locale: self.config.language,
+                            },
+                            enabled: false,
+                            text: TextOptions::default(),
+                            fallback: String::new(),
+                        });
+                        self.current_rect = self.fields.len() - 1;
+                    }
+                    ui.separator();
+
+                    for (i, field) in self.fields.iter().enumerate() {
+                        if ui
+                            .button(field_label(t, &self.columns, &field.kind))
+                            .clicked()
+                        {
                             self.current_rect = i;
                         }
                     }
                 });
+                ui.horizontal(|ui| {
+                    if ui.button(fix_text(t.export_mapping)).clicked()
+                        && self.mapping_export_receiver.is_none()
+                    {
+                        let (sender, receiver) = std::sync::mpsc::channel();
+                        self.mapping_export_receiver = Some(receiver);
+                        std::thread::spawn(move || {
+                            let current_dir = std::env::current_dir()?;
+
+                            let path = FileDialog::new()
+                                .set_location(&current_dir)
+                                .set_filename("mapping.json")
+                                .add_filter("Field mapping", &["json"])
+                                .show_save_single_file()?;
+
+                            sender.send(path)?;
+                            anyhow::Ok(())
+                        });
+                    }
+                    if ui.button(fix_text(t.import_mapping)).clicked()
+                        && self.mapping_import_receiver.is_none()
+                    {
+                        let (sender, receiver) = std::sync::mpsc::channel();
+                        self.mapping_import_receiver = Some(receiver);
+                        std::thread::spawn(move || {
+                            let current_dir = std::env::current_dir()?;
+
+                            let path = FileDialog::new()
+                                .set_location(&current_dir)
+                                .add_filter("Field mapping", &["json"])
+                                .show_open_single_file()?;
+
+                            sender.send(path)?;
+                            anyhow::Ok(())
+                        });
+                    }
+                    if let Some(status) = &self.mapping_status {
+                        ui.label(status);
+                    }
+                });
             });
-        egui::Window::new("Email Credentials")
-            .open(&mut self.email_window_open)
+        if refilter {
+            self.filter_records();
+        }
+        self.export_mapping();
+        self.import_mapping();
+        self.import_template_variant()
+            .expect("pick template variant");
+        egui::Window::new(fix_text(t.templates))
+            .open(&mut self.templates_window_open)
             .anchor(Align2::CENTER_CENTER, [0., 0.])
             .resizable(false)
             .collapsible(false)
             .show(ctx, |ui| {
-                ui.label("Email");
-                ui.text_edit_singleline(&mut self.current_email_creds.username);
-                ui.label("Password");
-                ui.add(
-                    egui::TextEdit::singleline(&mut self.current_email_creds.password)
-                        .password(true),
-                );
+                egui::ComboBox::from_label(fix_text(t.pick_template_by_column))
+                    .selected_text(match self.template_column {
+                        Some(i) => fix_text(&self.columns[i]),
+                        None => fix_text(t.none),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.template_column, None, fix_text(t.none));
+                        for (i, column) in self.columns.iter().enumerate() {
+                            ui.selectable_value(
+                                &mut self.template_column,
+                                Some(i),
+                                fix_text(column),
+                            );
+                        }
+                    });
+                let Some(column) = self.template_column else {
+                    ui.label(fix_text(t.every_certificate_imported));
+                    return;
+                };
+                if let Some(error) = &self.templates_error {
+                    ui.label(format!("{}: {error}", fix_text(t.invalid_template)));
+                }
+
+                let values = self
+                    .records
+                    .iter()
+                    .map(|record| &record[column])
+                    .collect::<BTreeSet<_>>();
+                egui::Grid::new("Templates").num_columns(3).show(ui, |ui| {
+                    for value in values {
+                        ui.label(fix_text(value));
+                        match self.templates.get(value) {
+                            Some(template) => {
+                                let ISize { width, height } = template.dimensions();
+                                let label = ui.label(format!("{width} x {height} px"));
+                                if template.dimensions() != self.template_dimensions {
+                                    label.on_hover_text(fix_text(t.differs_from_layout));
+                                }
+                            }
+                            None => {
+                                ui.label(fix_text(t.imported_template));
+                            }
+                        }
+                        if ui.button(fix_text(t.browse)).clicked()
+                            && self.templates_picker_receiver.is_none()
+                        {
+                            self.templates_picker_receiver = Some((
+                                value.to_string(),
+                                pick_template(None, self.config.pdf_dpi, self.pixels_per_point),
+                            ));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        if let Some(receiver) = self.output_dir_picker_receiver.take() {
+            match receiver.try_recv() {
+                Ok(path) => {
+                    if let Some(path) = path {
+                        self.preferences.output_dir = path;
+                    }
+                }
+                Err(TryRecvError::Empty) => self.output_dir_picker_receiver = Some(receiver),
+                Err(e) => panic!("{e}"),
+            }
+        }
+
+        egui::Window::new(fix_text(t.preferences))
+            .open(&mut self.preferences_window_open)
+            .anchor(Align2::CENTER_CENTER, [0., 0.])
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let preferences = &mut self.preferences;
+                egui::Grid::new("Preferences")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label(fix_text(t.output_directory));
+                        ui.horizontal(|ui| {
+                            ui.label(preferences.output_dir.display().to_string());
+                            if ui.button(fix_text(t.browse)).clicked()
+                                && self.output_dir_picker_receiver.is_none()
+                            {
+                                let (sender, receiver) = std::sync::mpsc::channel();
+                                self.output_dir_picker_receiver = Some(receiver);
+                                std::thread::spawn(move || {
+                                    let current_dir = std::env::current_dir()?;
+
+                                    let path = FileDialog::new()
+                                        .set_location(&current_dir)
+                                        .show_open_single_dir()?;
+
+                                    sender.send(path)?;
+                                    anyhow::Ok(())
+                                });
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label(fix_text(t.output_format));
+                        egui::ComboBox::from_id_source("Output format")
+                            .selected_text(preferences.format.extension().to_uppercase())
+                            .show_ui(ui, |ui| {
+                                for format in
+                                    [OutputFormat::Png, OutputFormat::Jpeg, OutputFormat::Webp]
+                                {
+                                    ui.selectable_value(
+                                        &mut preferences.format,
+                                        format,
+                                        format.extension().to_uppercase(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label(fix_text(t.quality));
+                        ui.add_enabled(
+                            preferences.format != OutputFormat::Png,
+                            egui::Slider::new(&mut preferences.quality, 1..=100),
+                        );
+                        ui.end_row();
+
+                        ui.label(fix_text(t.output_scale));
+                        ui.add(
+                            egui::DragValue::new(&mut preferences.output_scale)
+                                .speed(0.05)
+                                .clamp_range(0.1..=4.)
+                                .suffix("x"),
+                        )
+                        .on_hover_text(fix_text(t.output_scale_hint));
+                        ui.end_row();
+
+                        ui.label(fix_text(t.supersample));
+                        egui::ComboBox::from_id_source("supersample")
+                            .selected_text(format!("{}x", preferences.supersample))
+                            .show_ui(ui, |ui| {
+                                for factor in 1..=4 {
+                                    ui.selectable_value(
+                                        &mut preferences.supersample,
+                                        factor,
+                                        format!("{factor}x"),
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text(fix_text(t.supersample_hint));
+                        ui.end_row();
+
+                        ui.label(fix_text(t.background));
+                        ui.color_edit_button_srgb(&mut preferences.background);
+                        ui.end_row();
+
+                        ui.label(fix_text(t.pdf_dpi));
+                        ui.add(
+                            egui::DragValue::new(&mut preferences.pdf_dpi)
+                                .clamp_range(72..=1200)
+                                .suffix(" DPI"),
+                        )
+                        .on_hover_text(fix_text(t.pdf_dpi_hint));
+                        ui.end_row();
+
+                        ui.label(fix_text(t.font));
+                        ui.text_edit_singleline(&mut preferences.font_family);
+                        ui.end_row();
+
+                        ui.label(fix_text(t.font_size));
+                        ui.add(egui::Slider::new(&mut preferences.font_size, 0.0..=100.));
+                        ui.end_row();
+
+                        ui.label(fix_text(t.theme));
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(
+                                &mut preferences.theme,
+                                ThemePreference::System,
+                                fix_text(t.system),
+                            );
+                            ui.selectable_value(
+                                &mut preferences.theme,
+                                ThemePreference::Light,
+                                fix_text(t.light),
+                            );
+                            ui.selectable_value(
+                                &mut preferences.theme,
+                                ThemePreference::Dark,
+                                fix_text(t.dark),
+                            );
+                        });
+                        ui.end_row();
+
+                        ui.label(fix_text(t.language));
+                        ui.horizontal(|ui| {
+                            for language in [Language::English, Language::Arabic] {
+                                ui.selectable_value(
+                                    &mut preferences.language,
+                                    language,
+                                    fix_text(language.name()),
+                                );
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label(fix_text(t.manifest));
+                        ui.checkbox(&mut preferences.manifest, "manifest.json")
+                            .on_hover_text(fix_text(t.manifest_hint));
+                        ui.end_row();
+
+                        ui.label(fix_text(t.in_order));
+                        ui.checkbox(&mut preferences.in_order, "")
+                            .on_hover_text(fix_text(t.in_order_hint));
+                        ui.end_row();
+
+                        ui.label(fix_text(t.log_level));
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source("log_level")
+                                .selected_text(preferences.log_level.as_str())
+                                .show_ui(ui, |ui| {
+                                    for level in LevelFilter::iter() {
+                                        ui.selectable_value(
+                                            &mut preferences.log_level,
+                                            level,
+                                            level.as_str(),
+                                        );
+                                    }
+                                });
+                            ui.checkbox(&mut preferences.log_to_file, fix_text(t.log_to_file))
+                                .on_hover_text(format!("{}: certs.log", fix_text(t.log_file_hint)));
+                        });
+                        ui.end_row();
+
+                        ui.label(fix_text(t.threads));
+                        ui.add(
+                            egui::DragValue::new(&mut preferences.threads)
+                                .clamp_range(1..=num_cpus()),
+                        );
+                        ui.end_row();
+
+                        ui.label(fix_text(t.send_threads));
+                        ui.add(
+                            egui::DragValue::new(&mut preferences.send_threads).clamp_range(1..=8),
+                        )
+                        .on_hover_text(fix_text(t.send_threads_hint));
+                        ui.end_row();
+
+                        #[cfg(not(feature = "baba"))]
+                        {
+                            ui.label(fix_text(t.smtp_server));
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut preferences.smtp.host);
+                                ui.add(
+                                    egui::DragValue::new(&mut preferences.smtp.port)
+                                        .prefix(format!("{}: ", fix_text(t.port))),
+                                );
+                            });
+                            ui.end_row();
+
+                            ui.label(fix_text(t.email));
+                            ui.text_edit_singleline(&mut preferences.email.username);
+                            ui.end_row();
+
+                            ui.label(fix_text(t.display_name));
+                            ui.text_edit_singleline(&mut preferences.email.display_name)
+                                .on_hover_text(fix_text(t.display_name_hint));
+                            ui.end_row();
+
+                            ui.label(fix_text(t.reply_to));
+                            ui.text_edit_singleline(&mut preferences.reply_to);
+                            ui.end_row();
+
+                            ui.label(fix_text(t.organization));
+                            ui.text_edit_singleline(&mut preferences.organization);
+                            ui.end_row();
+
+                            ui.label(fix_text(t.attachment_name));
+                            ui.text_edit_singleline(&mut preferences.attachment_name)
+                                .on_hover_text(format!(
+                                    "{column_hint}\n{}",
+                                    fix_text(t.attachment_name_hint)
+                                ));
+                            ui.end_row();
+
+                            ui.label(fix_text(t.email_body));
+                            ui.text_edit_multiline(&mut preferences.email_body)
+                                .on_hover_text(column_hint.clone());
+                            ui.end_row();
+
+                            ui.label(fix_text(t.password));
+                            ui.add(
+                                egui::TextEdit::singleline(&mut preferences.email.password)
+                                    .password(true),
+                            );
+                            ui.end_row();
+                        }
+                    });
                 ui.horizontal(|ui| {
-                    if ui.button("Save").clicked() {
-                        let config_dir =
-                            dirs::config_dir().expect("config directory").join("certs/");
-                        self.config.email = self.current_email_creds.clone();
-                        let current_config =
-                            toml::to_string(&self.config).expect("Config to string");
-                        fs::write(config_dir.join("config.toml"), current_config)
-                            .expect("save config");
-                    }
-                    if ui.button("Clear").clicked() {
-                        let config_dir =
-                            dirs::config_dir().expect("config directory").join("certs/");
+                    if ui.button(fix_text(t.save)).clicked() {
+                        if preferences.font_size != self.config.font_size {
+                            self.font_size = preferences.font_size;
+                        }
+                        if preferences.email.username != self.config.email.username {
+                            if let Err(e) = self.config.email.delete_password() {
+                                warn!("{e:#}");
+                            }
+                        }
+                        if let Err(e) = preferences.email.store_password() {
+                            warn!("{e:#}");
+                        }
+                        self.config = preferences.clone();
+                        save_config(&self.config);
+                        configure_logging(&self.config);
+                        apply_theme(ctx, self.config.theme, self.system_theme);
+                    }
+                    #[cfg(not(feature = "baba"))]
+                    if ui
+                        .add_enabled(
+                            self.smtp_test_receiver.is_none(),
+                            Button::new(fix_text(t.test_connection)),
+                        )
+                        .clicked()
+                    {
+                        let (sender, receiver) = std::sync::mpsc::channel();
+                        let config = preferences.clone();
+                        std::thread::spawn(move || sender.send(certs::test_connection(&config)));
+                        self.smtp_test_receiver = Some(receiver);
+                        self.smtp_test = None;
+                    }
+                    #[cfg(not(feature = "baba"))]
+                    if ui.button(fix_text(t.clear_credentials)).clicked() {
+                        if let Err(e) = self.config.email.delete_password() {
+                            warn!("{e:#}");
+                        }
                         self.config.email = EmailCreds::default();
-                        self.current_email_creds = EmailCreds::default();
-                        let current_config =
-                            toml::to_string(&self.config).expect("Config to string");
-                        fs::write(config_dir.join("config.toml"), current_config)
-                            .expect("save config");
+                        preferences.email = EmailCreds::default();
+                        save_config(&self.config);
                     }
-                })
+                });
+                if let Some(receiver) = &self.smtp_test_receiver {
+                    match receiver.try_recv() {
+                        Ok(result) => {
+                            self.smtp_test = Some(match result {
+                                Ok(()) => fix_text(t.connected),
+                                Err(e) => format!("{e:#}"),
+                            });
+                            self.smtp_test_receiver = None;
+                        }
+                        Err(TryRecvError::Empty) => {
+                            ui.spinner();
+                        }
+                        Err(e) => panic!("{e}"),
+                    }
+                }
+                if let Some(result) = &self.smtp_test {
+                    ui.label(result);
+                }
+            });
+
+        let mut existing_files = None;
+        let mut cancel = false;
+        egui::Window::new(fix_text(t.existing_certificates))
+            .open(&mut self.existing_files_window_open)
+            .anchor(Align2::CENTER_CENTER, [0., 0.])
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{}: {}",
+                    fix_text(t.certificates_already_exist),
+                    self.existing_files
+                ));
+                ui.label(format!(
+                    "{}: {}",
+                    fix_text(t.output_directory),
+                    self.config.output_dir.display()
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button(fix_text(t.overwrite)).clicked() {
+                        existing_files = Some(ExistingFiles::Overwrite);
+                    }
+                    if ui.button(fix_text(t.skip_existing)).clicked() {
+                        existing_files = Some(ExistingFiles::Skip);
+                    }
+                    if ui.button(fix_text(t.new_folder)).clicked() {
+                        existing_files = Some(ExistingFiles::NewFolder);
+                    }
+                    if ui.button(fix_text(t.cancel)).clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+        if cancel {
+            self.existing_files_window_open = false;
+        }
+        if let Some(existing_files) = existing_files {
+            self.existing_files_window_open = false;
+            self.generate_certificates(Some(existing_files), None)
+                .expect("certificates");
+        }
+
+        let mut step = 0;
+        if self.preview_window_open && !ctx.wants_keyboard_input() {
+            let input = ctx.input();
+            if input.key_pressed(Key::ArrowRight) || input.key_pressed(Key::ArrowDown) {
+                step = 1;
+            } else if input.key_pressed(Key::ArrowLeft) || input.key_pressed(Key::ArrowUp) {
+                step = -1;
+            }
+        }
+        egui::Window::new(fix_text(t.preview))
+            .open(&mut self.preview_window_open)
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| match &self.preview {
+                Some(preview) => {
+                    if let Some(row) = self.previewed_row {
+                        ui.with_layout(row_layout(self.config.language), |ui| {
+                            if ui.button(fix_text(t.previous)).clicked() {
+                                step = -1;
+                            }
+                            ui.label(format!("{} / {}", row + 1, self.records.len()));
+                            if ui.button(fix_text(t.next)).clicked() {
+                                step = 1;
+                            }
+                        })
+                        .response
+                        .on_hover_text(fix_text(t.arrow_keys_hint));
+                    }
+                    for warning in &self.glyph_warnings {
+                        ui.colored_label(Color32::YELLOW, glyph_warning_text(t, warning));
+                    }
+                    egui::ScrollArea::both().show(ui, |ui| {
+                        ui.image(preview.texture_id(ctx), preview.size_vec2());
+                    });
+                }
+                None => {
+                    ui.label(self.status.clone());
+                }
+            });
+
+        if step != 0 {
+            self.step_preview(step);
+        }
+
+        let mut duplicates = None;
+        let mut cancel = false;
+        egui::Window::new(fix_text(t.duplicate_recipients))
+            .open(&mut self.duplicates_window_open)
+            .anchor(Align2::CENTER_CENTER, [0., 0.])
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(300.)
+                    .show(ui, |ui| {
+                        for (address, count) in &self.duplicates {
+                            ui.label(format!("{address} ({count})"));
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    if ui.button(fix_text(t.send_once)).clicked() {
+                        duplicates = Some(Duplicates::SendOnce);
+                    }
+                    if ui.button(fix_text(t.send_all)).clicked() {
+                        duplicates = Some(Duplicates::SendAll);
+                    }
+                    if ui.button(fix_text(t.cancel)).clicked() {
+                        cancel = true;
+                    }
+                });
             });
+        if cancel {
+            self.duplicates_window_open = false;
+        }
+        if let Some(duplicates) = duplicates {
+            self.duplicates_window_open = false;
+            self.send_emails(Some(duplicates), None)
+                .expect("Send Emails");
+        }
 
-        egui::Window::new("Create Certificates")
+        let mut retry_failed = None;
+        egui::Window::new(fix_text(t.create_certificates))
             .open(&mut self.certificates_window_open)
             .anchor(Align2::CENTER_CENTER, [0., 0.])
             .resizable(false)
             .collapsible(false)
             .show(ctx, |ui| {
                 ui.label(self.status.clone());
+                if let (None, Some(summary)) = (&self.t_handle, &self.summary) {
+                    show_summary(ui, t, summary);
+                    let failed_rows = &self.failed_rows.lock().expect("failed rows lock").rows;
+                    if !failed_rows.is_empty()
+                        && ui
+                            .button(format!(
+                                "{} ({})",
+                                fix_text(t.retry_failed),
+                                failed_rows.len()
+                            ))
+                            .on_hover_text(fix_text(t.retry_failed_hint))
+                            .clicked()
+                    {
+                        let mut rows = failed_rows.clone();
+                        rows.sort_unstable();
+                        retry_failed = Some(rows);
+                    }
+                }
+                if let Some(output_dir) = &self.last_output_dir {
+                    if self.t_handle.is_none()
+                        && ui.button(fix_text(t.open_output_folder)).clicked()
+                    {
+                        if let Err(e) = opener::open(output_dir) {
+                            self.status = format!("{}: {e}", fix_text(t.open_folder_failed));
+                        }
+                    }
+                }
             });
 
-        egui::Window::new("Send Email")
+        if let Some(rows) = retry_failed {
+            self.generate_certificates(None, Some(rows))
+                .expect("certificates");
+        }
+
+        egui::Window::new(fix_text(t.send_email))
             .open(&mut self.send_email_window_open)
             .anchor(Align2::CENTER_CENTER, [0., 0.])
             .resizable(false)
             .collapsible(false)
             .show(ctx, |ui| {
                 if self.config.email.username.is_empty() || self.config.email.password.is_empty() {
-                    ui.label("Add Email credentials");
+                    ui.label(fix_text(t.add_email_credentials));
                 } else {
                     ui.label(self.status.clone());
+                    if let (None, Some(summary)) = (&self.t_handle, &self.summary) {
+                        show_summary(ui, t, summary);
+                    }
                 }
             });
 
-        if let Some(t_handle) = &self.t_handle {
+        let mut confirmed = None;
+        let mut cancel = false;
+        if let Some(confirmation) = &self.send_confirmation {
+            egui::Window::new(fix_text(t.confirm_send))
+                .anchor(Align2::CENTER_CENTER, [0., 0.])
+                .resizable(false)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    let email = &self.config.email;
+                    let from = if email.display_name.is_empty() {
+                        email.username.clone()
+                    } else {
+                        format!("{} <{}>", email.display_name, email.username)
+                    };
+                    egui::Grid::new("send_confirmation")
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label(fix_text(t.recipients));
+                            ui.label(confirmation.recipients.to_string());
+                            ui.end_row();
+
+                            ui.label(fix_text(t.email_column));
+                            ui.label(fix_text(&confirmation.email_column));
+                            ui.end_row();
+
+                            ui.label(fix_text(t.from));
+                            ui.label(fix_text(&from));
+                            ui.end_row();
+
+                            ui.label(fix_text(t.subject));
+                            ui.label(fix_text(EMAIL_SUBJECT.trim()));
+                            ui.end_row();
+
+                            ui.label(fix_text(t.output_directory));
+                            ui.label(confirmation.output_dir.display().to_string());
+                            ui.end_row();
+                        });
+                    ui.horizontal(|ui| {
+                        if ui.button(fix_text(t.send)).clicked() {
+                            confirmed =
+                                Some((confirmation.duplicates, confirmation.output_dir.clone()));
+                        }
+                        if ui.button(fix_text(t.cancel)).clicked() {
+                            cancel = true;
+                        }
+                    });
+                });
+        }
+        if cancel {
+            self.send_confirmation = None;
+        }
+        if let Some((duplicates, output_dir)) = confirmed {
+            self.send_confirmation = None;
+            self.send_emails(duplicates, Some(output_dir))
+                .expect("Send Emails");
+        }
+
+        let mut restore = None;
+        let mut discard = false;
+        if self.restore.is_some() {
+            egui::Window::new(fix_text(t.restore_layout))
+                .anchor(Align2::CENTER_CENTER, [0., 0.])
+                .resizable(false)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label(fix_text(t.restore_prompt));
+                    ui.horizontal(|ui| {
+                        if ui.button(fix_text(t.restore)).clicked() {
+                            restore = self.restore.take();
+                        }
+                        if ui.button(fix_text(t.discard)).clicked() {
+                            discard = true;
+                        }
+                    });
+                });
+        }
+        if let Some(layout) = restore {
+            self.restore_layout(layout);
+        }
+        if discard {
+            self.restore = None;
+            if let Err(e) = fs::remove_file(autosave_path()) {
+                warn!("{e}");
+            }
+        }
+
+        if self.csv_file_picker_receiver.is_none() && self.image_file_picker_receiver.is_none() {
+            if let Some(layout) = self.pending_layout.take() {
+                self.apply_layout(layout);
+            }
+        }
+        // don't overwrite an autosave the user hasn't decided on yet
+        if self.restore.is_none() {
+            self.autosave();
+            ctx.request_repaint_after(AUTOSAVE_INTERVAL);
+        }
+
+        if let Some(t_handle) = self.t_handle.take() {
             if t_handle.is_finished() {
-                self.status = String::from("Finished!");
-                self.t_handle = None;
+                let (started, total) = self.run_started.take().unwrap_or((Instant::now(), 0));
+                match t_handle.join() {
+                    Ok(failures) => {
+                        self.status = fix_text(t.finished);
+                        self.summary = Some(RunSummary {
+                            total,
+                            failures,
+                            overflows: std::mem::take(
+                                &mut self.overflows.lock().expect("overflow log lock"),
+                            ),
+                            shortened: std::mem::take(&mut self.shortened),
+                            elapsed: started.elapsed(),
+                        });
+                    }
+                    Err(_) => self.status = fix_text(t.worker_panicked),
+                }
+            } else {
+                self.t_handle = Some(t_handle);
+                // keep the table's send statuses moving while the workers run
+                ctx.request_repaint_after(Duration::from_millis(250));
             }
         }
     }